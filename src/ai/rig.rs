@@ -1,24 +1,89 @@
+use crate::config::{AiConfig, AiProvider};
+use crate::db::TableSchema;
 use crate::error::AppError;
 use crate::handlers::FullSchema;
+use anyhow::{Context, anyhow};
 use rig::OneOrMany;
-use rig::completion::Chat;
+use rig::completion::{Chat, PromptError};
 use rig::message::Message;
 use rig::message::{AssistantContent, UserContent};
-use rig::providers::openai as rig_openai;
-use tracing::{error, info, instrument};
+use rig::providers::{azure as rig_azure, openai as rig_openai};
+use tracing::{error, info, instrument, warn};
+
+/// Chat client for the configured AI provider
+#[derive(Clone)]
+pub enum AiClient {
+    OpenAi(rig_openai::Client),
+    Azure(rig_azure::Client),
+}
+
+impl AiClient {
+    /// Build the client for the configured provider. Fails if the provider's
+    /// API key is not set, instead of panicking on first use.
+    pub fn try_new(config: &AiConfig) -> anyhow::Result<Self> {
+        match config.provider {
+            AiProvider::OpenAi => {
+                let api_key = std::env::var("OPENAI_API_KEY")
+                    .context("AI is enabled but OPENAI_API_KEY is not set")?;
+                let client = match &config.base_url {
+                    Some(base_url) => rig_openai::Client::from_url(&api_key, base_url),
+                    None => rig_openai::Client::new(&api_key),
+                };
+                Ok(AiClient::OpenAi(client))
+            }
+            AiProvider::Azure => {
+                let api_key = std::env::var("AZURE_API_KEY")
+                    .context("AI is enabled but AZURE_API_KEY is not set")?;
+                let endpoint = config
+                    .base_url
+                    .as_deref()
+                    .ok_or_else(|| anyhow!("ai.base_url (the Azure endpoint) is required"))?;
+                Ok(AiClient::Azure(rig_azure::Client::new(
+                    rig_azure::AzureOpenAIAuth::ApiKey(api_key),
+                    &config.api_version,
+                    endpoint,
+                )))
+            }
+        }
+    }
+
+    async fn chat(
+        &self,
+        model: &str,
+        prompt: Message,
+        history: Vec<Message>,
+    ) -> Result<String, PromptError> {
+        match self {
+            AiClient::OpenAi(client) => client.agent(model).build().chat(prompt, history).await,
+            AiClient::Azure(client) => client.agent(model).build().chat(prompt, history).await,
+        }
+    }
+}
+
+/// A previously generated query and the error it failed with, fed back to
+/// the model so it can correct itself
+#[derive(Debug, Clone, Copy)]
+pub struct RepairContext<'a> {
+    pub query: &'a str,
+    pub error: &'a str,
+}
 
 // Placeholder for the AI query generation logic
-#[instrument(skip(openai_client, schema), fields(db_name = %db_name))]
+#[instrument(skip(ai_client, ai_config, schema, tables, repair), fields(db_name = %db_name))]
 pub async fn generate_sql_query(
-    openai_client: &rig_openai::Client,
+    ai_client: &AiClient,
+    ai_config: &AiConfig,
     db_name: &str,
     schema: &FullSchema, // Or maybe just DatabaseSchema?
+    tables: Option<&[String]>,
     prompt: &str,
+    repair: Option<RepairContext<'_>>,
 ) -> Result<String, AppError> {
     info!("Generating SQL query using AI for database: {}", db_name);
 
     // TODO: 1. Format the schema into a string (e.g., Markdown)
-    let schema_string = format_schema_for_prompt(schema, db_name)?;
+    let schema_string =
+        format_schema_for_prompt(schema, db_name, tables, ai_config.schema_char_budget)?;
 
     // Construct the prompt using rig::completion::Prompt
     // System prompt provides context and instructions
@@ -30,16 +95,19 @@ pub async fn generate_sql_query(
         db_name, schema_string
     );
 
-    // User prompt contains the specific request
-    let user_prompt = prompt.to_string();
+    // User prompt contains the specific request, plus the failed attempt if any
+    let user_prompt = match repair {
+        Some(RepairContext { query, error }) => format!(
+            "{}\n\nThe previous query\n{}\nfailed with this error:\n{}\nReturn a corrected query.",
+            prompt, query, error
+        ),
+        None => prompt.to_string(),
+    };
 
-    // Define the model to use (e.g., gpt-4o)
-    let model = "gpt-4o";
+    // Use the configured model (e.g., gpt-4o)
+    let model = &ai_config.model;
     info!("Prompting model '{}'", model);
 
-    // Build the agent and send the prompt
-    let agent = openai_client.agent(model).build();
-
     // Construct messages for the chat API
     let messages = vec![Message::Assistant {
         content: OneOrMany::one(AssistantContent::Text(system_prompt.into())),
@@ -49,7 +117,7 @@ pub async fn generate_sql_query(
         content: OneOrMany::one(UserContent::Text(user_prompt.into())),
     };
 
-    match agent.chat(prompt, messages).await {
+    match ai_client.chat(model, prompt, messages).await {
         Ok(response) => {
             info!("Successfully received response from AI model.");
             if response.is_empty() {
@@ -59,11 +127,12 @@ pub async fn generate_sql_query(
                 ));
             }
 
-            info!("Generated SQL query: {}", response);
-            Ok(response)
+            let sql = clean_sql_response(&response)?;
+            info!("Generated SQL query: {}", sql);
+            Ok(sql)
         }
         Err(e) => {
-            error!("Error calling OpenAI API: {}", e);
+            error!("Error calling AI provider: {}", e);
             // Convert rig::Error into AppError::AiError
             Err(AppError::AiError(format!(
                 "Failed to generate query: {}",
@@ -73,8 +142,46 @@ pub async fn generate_sql_query(
     }
 }
 
+/// Keywords a generated query may start with
+const SELECT_KEYWORDS: [&str; 3] = ["SELECT", "WITH", "EXPLAIN"];
+
+/// Strip a surrounding ```sql fence the model may add despite instructions,
+/// and make sure what remains looks like a read query.
+fn clean_sql_response(response: &str) -> Result<String, AppError> {
+    let mut sql = response.trim();
+    if let Some(rest) = sql.strip_prefix("```") {
+        // Drop the optional language tag on the opening fence line
+        let rest = match rest.find('\n') {
+            Some(pos) if !rest[..pos].contains(' ') => &rest[pos + 1..],
+            _ => rest,
+        };
+        sql = rest.trim_end().strip_suffix("```").unwrap_or(rest).trim();
+    }
+
+    let first_word = sql
+        .split(|c: char| c.is_whitespace() || c == '(')
+        .next()
+        .unwrap_or_default();
+    if !SELECT_KEYWORDS
+        .iter()
+        .any(|kw| first_word.eq_ignore_ascii_case(kw))
+    {
+        error!("AI response is not a SELECT query: {}", response);
+        return Err(AppError::AiError(format!(
+            "AI did not return a SELECT query: {}",
+            response
+        )));
+    }
+    Ok(sql.to_string())
+}
+
 // Placeholder for schema formatting logic
-fn format_schema_for_prompt(schema: &FullSchema, db_name: &str) -> Result<String, AppError> {
+pub fn format_schema_for_prompt(
+    schema: &FullSchema,
+    db_name: &str,
+    tables: Option<&[String]>,
+    char_budget: usize,
+) -> Result<String, AppError> {
     // Find the specific database schema
     let db_schema = schema
         .databases
@@ -82,10 +189,19 @@ fn format_schema_for_prompt(schema: &FullSchema, db_name: &str) -> Result<String
         .find(|db| db.name == db_name)
         .ok_or_else(|| AppError::NotFound(format!("Schema not found for database: {}", db_name)))?;
 
+    let selected: Vec<&TableSchema> = match tables {
+        Some(wanted) => select_tables(&db_schema.tables, wanted),
+        None => db_schema.tables.iter().collect(),
+    };
+
     // Simple Markdown formatting (can be enhanced)
     let mut markdown = format!("# Database: {}\n\n", db_schema.name);
-    for table in &db_schema.tables {
+    for table in selected {
         markdown.push_str(&format!("## Table: {}\n", table.table_name));
+        // Comments carry the business meaning names alone don't
+        if let Some(description) = &table.description {
+            markdown.push_str(&format!("{}\n\n", description));
+        }
         markdown.push_str("| Column | Type | Nullable | PK | FK |\n");
         markdown.push_str("|---|---|---|---|---|\n");
         for col in &table.columns {
@@ -102,29 +218,118 @@ fn format_schema_for_prompt(schema: &FullSchema, db_name: &str) -> Result<String
                 ))
             ));
         }
+        // Cardinality hints help the model pick selective filters and joins
+        let hints: Vec<String> = table
+            .columns
+            .iter()
+            .filter_map(|col| {
+                let stats = col.stats.as_ref()?;
+                let mut parts = Vec::new();
+                if let Some(distinct) = stats.distinct_estimate {
+                    parts.push(format!("~{:.0} distinct", distinct));
+                }
+                if let Some(null_fraction) = stats.null_fraction {
+                    parts.push(format!("{:.0}% null", null_fraction * 100.0));
+                }
+                (!parts.is_empty()).then(|| format!("{} ({})", col.name, parts.join(", ")))
+            })
+            .collect();
+        if !hints.is_empty() {
+            markdown.push_str(&format!("\nStatistics: {}\n", hints.join("; ")));
+        }
+        // Enum labels keep the model from inventing values in filters
+        let enums: Vec<String> = table
+            .columns
+            .iter()
+            .filter_map(|col| {
+                let values = col.enum_values.as_ref()?;
+                Some(format!("{} ({})", col.name, values.join(", ")))
+            })
+            .collect();
+        if !enums.is_empty() {
+            markdown.push_str(&format!("\nAllowed values: {}\n", enums.join("; ")));
+        }
+        let descriptions: Vec<String> = table
+            .columns
+            .iter()
+            .filter_map(|col| Some(format!("{}: {}", col.name, col.description.as_ref()?)))
+            .collect();
+        if !descriptions.is_empty() {
+            markdown.push_str(&format!(
+                "\nColumn descriptions: {}\n",
+                descriptions.join("; ")
+            ));
+        }
         markdown.push('\n');
     }
 
+    if markdown.len() > char_budget {
+        warn!(
+            "Schema for '{}' is {} characters, truncating to {}",
+            db_name,
+            markdown.len(),
+            char_budget
+        );
+        // Cut at the last full line that fits the budget
+        let mut end = char_budget;
+        while !markdown.is_char_boundary(end) {
+            end -= 1;
+        }
+        let end = markdown[..end].rfind('\n').map_or(0, |i| i + 1);
+        markdown.truncate(end);
+        markdown.push_str("... (schema truncated)\n");
+    }
+
     Ok(markdown)
 }
 
+/// Keep the requested tables plus the tables their foreign keys reference,
+/// in schema order. Names match either exactly or by their unqualified part,
+/// so `users` selects `public.users`.
+pub fn select_tables<'a>(tables: &'a [TableSchema], wanted: &[String]) -> Vec<&'a TableSchema> {
+    let matches = |table: &TableSchema, name: &str| {
+        table.table_name == name
+            || table
+                .table_name
+                .rsplit_once('.')
+                .is_some_and(|(_, short)| short == name)
+    };
+
+    let requested: Vec<&TableSchema> = tables
+        .iter()
+        .filter(|t| wanted.iter().any(|name| matches(t, name)))
+        .collect();
+    let referenced: Vec<&str> = requested
+        .iter()
+        .flat_map(|t| t.columns.iter().filter_map(|c| c.fk_table.as_deref()))
+        .collect();
+
+    tables
+        .iter()
+        .filter(|t| {
+            requested.iter().any(|r| std::ptr::eq(*r, *t))
+                || referenced.iter().any(|name| matches(t, name))
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     // Import necessary structs directly from db and handlers
-    use crate::db::{ColumnInfo, ColumnType, TableSchema}; // Import directly from db
+    use crate::db::{ColumnInfo, ColumnStats, ColumnType, DatabaseType, TableSchema}; // Import directly from db
     use crate::handlers::{DatabaseSchema, FullSchema};
     use insta::assert_snapshot;
 
-    #[test]
-    fn test_format_schema_simple() {
-        // Arrange: Create mock schema data
+    fn test_schema() -> FullSchema {
         let db_schema = DatabaseSchema {
             name: "test_db".to_string(),
             db_type: "postgresql".to_string(),
+            kind: DatabaseType::Postgres,
             tables: vec![
                 TableSchema {
                     table_name: "users".to_string(),
+                    description: None,
                     columns: vec![
                         ColumnInfo {
                             name: "id".to_string(),
@@ -134,6 +339,9 @@ mod tests {
                             is_unique: false,
                             fk_table: None,
                             fk_column: None,
+                            stats: None,
+                            enum_values: None,
+                            description: None,
                         },
                         ColumnInfo {
                             name: "username".to_string(),
@@ -143,11 +351,15 @@ mod tests {
                             is_unique: true,
                             fk_table: None,
                             fk_column: None,
+                            stats: None,
+                            enum_values: None,
+                            description: None,
                         },
                     ],
                 },
                 TableSchema {
                     table_name: "posts".to_string(),
+                    description: None,
                     columns: vec![
                         ColumnInfo {
                             name: "post_id".to_string(),
@@ -157,6 +369,9 @@ mod tests {
                             is_unique: false,
                             fk_table: None,
                             fk_column: None,
+                            stats: None,
+                            enum_values: None,
+                            description: None,
                         },
                         ColumnInfo {
                             name: "user_id".to_string(),
@@ -166,6 +381,9 @@ mod tests {
                             is_unique: false,
                             fk_table: Some("users".to_string()),
                             fk_column: Some("id".to_string()),
+                            stats: None,
+                            enum_values: None,
+                            description: None,
                         },
                         ColumnInfo {
                             name: "content".to_string(),
@@ -175,30 +393,198 @@ mod tests {
                             is_unique: false,
                             fk_table: None,
                             fk_column: None,
+                            stats: None,
+                            enum_values: None,
+                            description: None,
                         },
                     ],
                 },
             ],
         };
-        let full_schema = FullSchema {
+        FullSchema {
             databases: vec![db_schema],
-        };
+        }
+    }
+
+    #[test]
+    fn test_format_schema_simple() {
+        // Arrange: Create mock schema data
+        let full_schema = test_schema();
 
         // Act: Call the function
-        let result = format_schema_for_prompt(&full_schema, "test_db");
+        let result = format_schema_for_prompt(&full_schema, "test_db", None, usize::MAX);
 
         // Assert: Check if successful and compare with snapshot
         assert!(result.is_ok());
         assert_snapshot!(result.unwrap());
     }
 
+    #[test]
+    fn test_format_schema_table_subset() {
+        let full_schema = test_schema();
+        let tables = vec!["posts".to_string()];
+        let markdown =
+            format_schema_for_prompt(&full_schema, "test_db", Some(&tables), usize::MAX).unwrap();
+        // posts.user_id references users, so users is kept as well
+        assert!(markdown.contains("## Table: posts"));
+        assert!(markdown.contains("## Table: users"));
+
+        let tables = vec!["users".to_string()];
+        let markdown =
+            format_schema_for_prompt(&full_schema, "test_db", Some(&tables), usize::MAX).unwrap();
+        assert!(markdown.contains("## Table: users"));
+        assert!(!markdown.contains("## Table: posts"));
+    }
+
+    #[test]
+    fn test_format_schema_truncated() {
+        let full_schema = test_schema();
+        let markdown = format_schema_for_prompt(&full_schema, "test_db", None, 200).unwrap();
+        assert!(markdown.ends_with("... (schema truncated)\n"));
+        assert!(markdown.len() <= 200 + "... (schema truncated)\n".len());
+    }
+
+    #[test]
+    fn test_format_schema_with_stats() {
+        let db_schema = DatabaseSchema {
+            name: "test_db".to_string(),
+            db_type: "postgresql".to_string(),
+            kind: DatabaseType::Postgres,
+            tables: vec![TableSchema {
+                table_name: "users".to_string(),
+                description: None,
+                columns: vec![ColumnInfo {
+                    name: "country".to_string(),
+                    data_type: ColumnType::Text,
+                    is_nullable: true,
+                    is_pk: false,
+                    is_unique: false,
+                    fk_table: None,
+                    fk_column: None,
+                    stats: Some(ColumnStats {
+                        distinct_estimate: Some(42.0),
+                        null_fraction: Some(0.25),
+                    }),
+                    enum_values: None,
+                    description: None,
+                }],
+            }],
+        };
+        let full_schema = FullSchema {
+            databases: vec![db_schema],
+        };
+
+        let markdown = format_schema_for_prompt(&full_schema, "test_db", None, usize::MAX).unwrap();
+        assert!(markdown.contains("Statistics: country (~42 distinct, 25% null)"));
+    }
+
+    #[test]
+    fn test_format_schema_with_enum_values() {
+        let db_schema = DatabaseSchema {
+            name: "test_db".to_string(),
+            db_type: "postgresql".to_string(),
+            kind: DatabaseType::Postgres,
+            tables: vec![TableSchema {
+                table_name: "users".to_string(),
+                description: None,
+                columns: vec![ColumnInfo {
+                    name: "status".to_string(),
+                    data_type: ColumnType::Other("user_status".to_string()),
+                    is_nullable: false,
+                    is_pk: false,
+                    is_unique: false,
+                    fk_table: None,
+                    fk_column: None,
+                    stats: None,
+                    enum_values: Some(vec!["active".to_string(), "banned".to_string()]),
+                    description: None,
+                }],
+            }],
+        };
+        let full_schema = FullSchema {
+            databases: vec![db_schema],
+        };
+
+        let markdown = format_schema_for_prompt(&full_schema, "test_db", None, usize::MAX).unwrap();
+        assert!(markdown.contains("Allowed values: status (active, banned)"));
+    }
+
+    #[test]
+    fn test_format_schema_with_descriptions() {
+        let db_schema = DatabaseSchema {
+            name: "test_db".to_string(),
+            db_type: "postgresql".to_string(),
+            kind: DatabaseType::Postgres,
+            tables: vec![TableSchema {
+                table_name: "orders".to_string(),
+                description: Some("Orders placed in the web shop".to_string()),
+                columns: vec![ColumnInfo {
+                    name: "total".to_string(),
+                    data_type: ColumnType::Numeric,
+                    is_nullable: false,
+                    is_pk: false,
+                    is_unique: false,
+                    fk_table: None,
+                    fk_column: None,
+                    stats: None,
+                    enum_values: None,
+                    description: Some("Gross amount in cents".to_string()),
+                }],
+            }],
+        };
+        let full_schema = FullSchema {
+            databases: vec![db_schema],
+        };
+
+        let markdown = format_schema_for_prompt(&full_schema, "test_db", None, usize::MAX).unwrap();
+        assert!(markdown.contains("## Table: orders\nOrders placed in the web shop\n\n"));
+        assert!(markdown.contains("Column descriptions: total: Gross amount in cents"));
+    }
+
+    #[test]
+    fn test_clean_sql_response() {
+        let cases = [
+            ("SELECT 1", "SELECT 1"),
+            ("  select id from users;\n", "select id from users;"),
+            ("```sql\nSELECT * FROM t\n```", "SELECT * FROM t"),
+            (
+                "```SQL\nWITH x AS (SELECT 1) SELECT * FROM x\n```\n",
+                "WITH x AS (SELECT 1) SELECT * FROM x",
+            ),
+            ("```\nSELECT 1\n```", "SELECT 1"),
+            ("```SELECT 1```", "SELECT 1"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                clean_sql_response(input).unwrap(),
+                expected,
+                "input: {input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_clean_sql_response_rejects_non_select() {
+        for input in [
+            "DELETE FROM users",
+            "```sql\nDROP TABLE t\n```",
+            "Sorry, I can't",
+            "",
+        ] {
+            assert!(matches!(
+                clean_sql_response(input),
+                Err(AppError::AiError(_))
+            ));
+        }
+    }
+
     #[test]
     fn test_format_schema_db_not_found() {
         // Arrange: Empty schema
         let full_schema = FullSchema { databases: vec![] };
 
         // Act: Call the function with a non-existent db name
-        let result = format_schema_for_prompt(&full_schema, "non_existent_db");
+        let result = format_schema_for_prompt(&full_schema, "non_existent_db", None, usize::MAX);
 
         // Assert: Check for NotFound error
         assert!(result.is_err());
@@ -0,0 +1,145 @@
+use crate::config::{AuditConfig, AuditSink};
+use anyhow::Context;
+use serde::Serialize;
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+use tokio::{fs::File, io::AsyncWriteExt, sync::Mutex};
+use tracing::error;
+
+/// Longest query text written to the audit log; longer ones are cut short
+const MAX_QUERY_CHARS: usize = 4096;
+
+/// One audited call. The query should already be redacted (see
+/// `TableAccess::redact_query`); it is truncated when written.
+#[derive(Debug, Serialize)]
+pub struct AuditRecord<'a> {
+    pub sub: &'a str,    // Token subject
+    pub action: &'a str, // Handler, e.g. "execute_query"
+    pub db_name: &'a str,
+    pub query: &'a str,
+    pub row_count: Option<u64>, // Rows returned or affected
+    pub execution_time: f64,    // Seconds
+    pub success: bool,
+}
+
+#[derive(Serialize)]
+struct AuditEntry<'a> {
+    timestamp: String, // RFC 3339, UTC
+    #[serde(flatten)]
+    record: AuditRecord<'a>,
+}
+
+/// Writes audit records as JSON lines to the configured sink
+pub enum AuditLog {
+    Off,
+    Stdout,
+    File(Mutex<File>),
+}
+
+impl AuditLog {
+    pub async fn try_new(config: &AuditConfig) -> Result<Self, anyhow::Error> {
+        Ok(match config.sink {
+            AuditSink::Off => AuditLog::Off,
+            AuditSink::Stdout => AuditLog::Stdout,
+            AuditSink::File => {
+                let path = config
+                    .path
+                    .as_deref()
+                    .context("audit.path is required for the file audit sink")?;
+                let file = File::options()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .await
+                    .with_context(|| format!("Failed to open audit log {}", path.display()))?;
+                AuditLog::File(Mutex::new(file))
+            }
+        })
+    }
+
+    /// Write a record. Failing to write is logged, never returned, so
+    /// auditing can't fail the request it records.
+    pub async fn record(&self, mut record: AuditRecord<'_>) {
+        if matches!(self, AuditLog::Off) {
+            return;
+        }
+        let truncated;
+        if let Some((end, _)) = record.query.char_indices().nth(MAX_QUERY_CHARS) {
+            truncated = format!("{}...", &record.query[..end]);
+            record.query = &truncated;
+        }
+        let entry = AuditEntry {
+            timestamp: OffsetDateTime::now_utc()
+                .format(&Rfc3339)
+                .unwrap_or_default(),
+            record,
+        };
+        let mut line = match serde_json::to_string(&entry) {
+            Ok(line) => line,
+            Err(e) => {
+                error!("Failed to serialize audit record: {}", e);
+                return;
+            }
+        };
+        line.push('\n');
+        match self {
+            AuditLog::Off => {}
+            AuditLog::Stdout => print!("{}", line),
+            AuditLog::File(file) => {
+                // Tokio hands writes to a background thread; flushing waits
+                // until the record has actually reached the file
+                let mut file = file.lock().await;
+                let written = match file.write_all(line.as_bytes()).await {
+                    Ok(()) => file.flush().await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = written {
+                    error!("Failed to write audit record: {}", e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file_sink() {
+        let path = std::env::temp_dir().join(format!("r2-audit-{}.jsonl", uuid::Uuid::new_v4()));
+        let log = AuditLog::try_new(&AuditConfig {
+            sink: AuditSink::File,
+            path: Some(path.clone()),
+        })
+        .await
+        .unwrap();
+        let long_query = format!("SELECT '{}'", "x".repeat(MAX_QUERY_CHARS));
+        for (query, success) in [("SELECT 1", true), (long_query.as_str(), false)] {
+            log.record(AuditRecord {
+                sub: "alice",
+                action: "execute_query",
+                db_name: "users",
+                query,
+                row_count: success.then_some(1),
+                execution_time: 0.5,
+                success,
+            })
+            .await;
+        }
+
+        let contents = tokio::fs::read_to_string(&path).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let entries: Vec<serde_json::Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["sub"], "alice");
+        assert_eq!(entries[0]["query"], "SELECT 1");
+        assert_eq!(entries[0]["row_count"], 1);
+        assert!(entries[0]["timestamp"].as_str().unwrap().ends_with('Z'));
+        assert_eq!(entries[1]["success"], false);
+        let query = entries[1]["query"].as_str().unwrap();
+        assert_eq!(query.chars().count(), MAX_QUERY_CHARS + 3);
+    }
+}
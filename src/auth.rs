@@ -1,21 +1,165 @@
+use crate::AppConfig;
 use crate::{error::AuthError, state::AppState};
+use anyhow::Context;
 use axum::{
+    Json,
     body::Body,
-    extract::State,
-    http::{HeaderMap, Request},
+    extract::{FromRequestParts, State},
+    http::{HeaderMap, Request, request::Parts},
     middleware::Next,
     response::Response,
 };
-use jsonwebtoken::{DecodingKey, Validation, decode};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation, decode, encode};
 use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
 
 // Define the structure of the JWT claims
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Claims {
     pub sub: String, // Subject (e.g., user ID or email)
     pub exp: usize,  // Expiration time (timestamp)
-                     // Add any other custom claims you might need
-                     // pub roles: Vec<String>,
+    /// Roles checked against a database's `required_roles`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+}
+
+/// The authenticated caller, extracted from the claims `auth_middleware`
+/// stores in the request extensions. Rejects with 401 when there are none,
+/// e.g. on a route outside the middleware.
+#[derive(Debug, Clone)]
+pub struct AuthedUser(pub Claims);
+
+impl<S: Send + Sync> FromRequestParts<S> for AuthedUser {
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<Claims>()
+            .cloned()
+            .map(AuthedUser)
+            .ok_or(AuthError::MissingCredentials)
+    }
+}
+
+/// Verifies bearer tokens using the key and rules from the configuration.
+/// Built once at startup so PEM keys aren't re-read on every request.
+pub struct JwtValidator {
+    decoding_key: DecodingKey,
+    validation: Validation,
+}
+
+impl JwtValidator {
+    pub fn try_new(config: &AppConfig) -> Result<Self, anyhow::Error> {
+        let algorithm = config.jwt_algorithm;
+        let decoding_key = if is_hmac(algorithm) {
+            DecodingKey::from_secret(config.jwt_secret.as_ref())
+        } else {
+            let path = config.jwt_public_key_path.as_deref().with_context(|| {
+                format!("jwt_public_key_path is required for {:?} tokens", algorithm)
+            })?;
+            let pem = std::fs::read(path)
+                .with_context(|| format!("Failed to read JWT public key from {}", path))?;
+            match algorithm {
+                Algorithm::ES256 | Algorithm::ES384 => DecodingKey::from_ec_pem(&pem)?,
+                Algorithm::EdDSA => DecodingKey::from_ed_pem(&pem)?,
+                _ => DecodingKey::from_rsa_pem(&pem)?,
+            }
+        };
+
+        let mut validation = Validation::new(algorithm);
+        let mut required_claims = vec!["exp"];
+        if let Some(audience) = &config.jwt_audience {
+            validation.set_audience(&[audience]);
+            required_claims.push("aud");
+        }
+        if let Some(issuer) = &config.jwt_issuer {
+            validation.set_issuer(&[issuer]);
+            required_claims.push("iss");
+        }
+        validation.set_required_spec_claims(&required_claims);
+
+        Ok(Self {
+            decoding_key,
+            validation,
+        })
+    }
+
+    pub fn validate(&self, token: &str) -> Result<Claims, AuthError> {
+        decode::<Claims>(token, &self.decoding_key, &self.validation)
+            .map(|data| data.claims)
+            .map_err(|e| AuthError::InvalidToken(e.to_string()))
+    }
+}
+
+fn is_hmac(algorithm: Algorithm) -> bool {
+    matches!(
+        algorithm,
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+    )
+}
+
+#[derive(Deserialize)]
+pub struct LoginRequest {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct LoginResponse {
+    pub token: String,
+}
+
+/// Exchange configured credentials for a signed JWT
+pub async fn login(
+    State(state): State<AppState>,
+    Json(payload): Json<LoginRequest>,
+) -> Result<Json<LoginResponse>, AuthError> {
+    let user = state
+        .config
+        .users
+        .iter()
+        .find(|user| user.username == payload.username && user.password == payload.password)
+        .ok_or(AuthError::MissingCredentials)?;
+
+    // With asymmetric algorithms tokens come from an external IdP holding the
+    // private key, so this service can't sign them itself
+    let config = &state.config;
+    if !is_hmac(config.jwt_algorithm) {
+        warn!(
+            "Login is unavailable for {:?} tokens, they must come from the IdP",
+            config.jwt_algorithm
+        );
+        return Err(AuthError::TokenCreation);
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| AuthError::TokenCreation)?;
+    let claims = Claims {
+        sub: payload.username,
+        exp: (now.as_secs() + config.token_ttl_secs) as usize,
+        roles: user.roles.clone(),
+    };
+    // Include aud/iss when configured so issued tokens pass our own validation
+    let mut token_claims = serde_json::to_value(&claims).map_err(|_| AuthError::TokenCreation)?;
+    if let Some(audience) = &config.jwt_audience {
+        token_claims["aud"] = audience.as_str().into();
+    }
+    if let Some(issuer) = &config.jwt_issuer {
+        token_claims["iss"] = issuer.as_str().into();
+    }
+    let encoding_key = EncodingKey::from_secret(config.jwt_secret.as_ref());
+    let token = encode(
+        &Header::new(config.jwt_algorithm),
+        &token_claims,
+        &encoding_key,
+    )
+    .map_err(|_| AuthError::TokenCreation)?;
+
+    info!("Issued token for user '{}'", claims.sub);
+    Ok(Json(LoginResponse { token }))
 }
 
 pub async fn auth_middleware(
@@ -31,13 +175,7 @@ pub async fn auth_middleware(
 
     let token = token.ok_or(AuthError::MissingCredentials)?;
 
-    let decoding_key = DecodingKey::from_secret(state.config.jwt_secret.as_ref());
-
-    let validation = Validation::default();
-
-    let claims = decode::<Claims>(token, &decoding_key, &validation)
-        .map_err(|e| AuthError::InvalidToken(e.to_string()))?
-        .claims;
+    let claims = state.jwt_validator.validate(token)?;
 
     // Store claims in request extensions for handlers to use if needed
     request.extensions_mut().insert(claims);
@@ -45,10 +183,28 @@ pub async fn auth_middleware(
     Ok(next.run(request).await)
 }
 
+/// Only let through subjects listed in `admins`; must run after
+/// `auth_middleware`, which stores the claims.
+pub async fn admin_middleware(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Result<Response, AuthError> {
+    let claims = request
+        .extensions()
+        .get::<Claims>()
+        .ok_or(AuthError::MissingCredentials)?;
+    if !state.config.admins.contains(&claims.sub) {
+        warn!("Rejected admin request from '{}'", claims.sub);
+        return Err(AuthError::Forbidden);
+    }
+    Ok(next.run(request).await)
+}
+
 // Add tests module
 #[cfg(test)]
 mod tests {
-    use crate::AppConfig;
+    use crate::config::UserCredentials;
 
     use super::*;
     use jsonwebtoken::{EncodingKey, Header, encode};
@@ -68,6 +224,7 @@ mod tests {
         let claims = Claims {
             sub: user_id.to_owned(),
             exp: expiration.as_secs() as usize,
+            roles: Vec::new(),
         };
 
         let header = Header::default(); // Default algorithm is HS256
@@ -94,4 +251,93 @@ mod tests {
         assert!(decoded.is_ok());
         assert_eq!(decoded.unwrap().claims.sub, user_id);
     }
+
+    #[tokio::test]
+    async fn test_login() {
+        let mut config = AppConfig::load("./config").unwrap();
+        config.users = vec![UserCredentials {
+            username: "alice".to_string(),
+            password: "wonderland".to_string(),
+            roles: vec!["analyst".to_string()],
+        }];
+        let secret = config.jwt_secret.clone();
+        let state = AppState::new_for_test(config);
+
+        // Valid credentials yield a token for the user
+        let Json(response) = login(
+            State(state.clone()),
+            Json(LoginRequest {
+                username: "alice".to_string(),
+                password: "wonderland".to_string(),
+            }),
+        )
+        .await
+        .unwrap();
+        let decoding_key = DecodingKey::from_secret(secret.as_ref());
+        let decoded = decode::<Claims>(&response.token, &decoding_key, &Validation::default());
+        let claims = decoded.unwrap().claims;
+        assert_eq!(claims.sub, "alice");
+        assert_eq!(claims.roles, ["analyst"]);
+
+        // Wrong password is rejected
+        let result = login(
+            State(state),
+            Json(LoginRequest {
+                username: "alice".to_string(),
+                password: "wrong".to_string(),
+            }),
+        )
+        .await;
+        assert!(matches!(result, Err(AuthError::MissingCredentials)));
+    }
+
+    #[test]
+    fn test_rs256_validation() {
+        let mut config = AppConfig::load("./config").unwrap();
+        config.jwt_algorithm = Algorithm::RS256;
+        config.jwt_public_key_path = Some("./fixtures/jwt/public.pem".to_string());
+        config.jwt_audience = Some("r2-data2".to_string());
+        let validator = JwtValidator::try_new(&config).unwrap();
+
+        let private_key = std::fs::read("./fixtures/jwt/private.pem").unwrap();
+        let encoding_key = EncodingKey::from_rsa_pem(&private_key).unwrap();
+        let exp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 3600;
+        let sign = |aud: &str| {
+            let claims = serde_json::json!({ "sub": "idp_user", "exp": exp, "aud": aud });
+            encode(&Header::new(Algorithm::RS256), &claims, &encoding_key).unwrap()
+        };
+
+        // A token for the configured audience is accepted
+        let claims = validator.validate(&sign("r2-data2")).unwrap();
+        assert_eq!(claims.sub, "idp_user");
+
+        // A token for another audience is rejected
+        assert!(validator.validate(&sign("other-app")).is_err());
+
+        // An HS256 token signed with the shared secret is rejected
+        let hs_token = generate_test_jwt("test_user", 3600).unwrap();
+        assert!(validator.validate(&hs_token).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_authed_user_without_claims() {
+        let (mut parts, _) = Request::new(()).into_parts();
+        let result = AuthedUser::from_request_parts(&mut parts, &()).await;
+        assert!(matches!(result, Err(AuthError::MissingCredentials)));
+
+        let claims = Claims {
+            sub: "alice".to_string(),
+            exp: usize::MAX,
+            roles: Vec::new(),
+        };
+        parts.extensions.insert(claims);
+        let AuthedUser(claims) = AuthedUser::from_request_parts(&mut parts, &())
+            .await
+            .unwrap();
+        assert_eq!(claims.sub, "alice");
+    }
 }
@@ -1,9 +1,17 @@
-use std::{fmt, path::Path, str::FromStr};
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-use config::{Config, Environment, File};
-use serde::{Deserialize, Serialize};
+use anyhow::{Context, bail};
+use axum::http::HeaderValue;
+use config::{Config, Environment, File, FileFormat};
+use jsonwebtoken::Algorithm;
+use serde::{Deserialize, Deserializer, Serialize};
 
-use crate::DatabaseType;
+use crate::{DatabaseType, db::RowLimits};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DatabaseConfig {
@@ -11,6 +19,118 @@ pub struct DatabaseConfig {
     #[serde(rename = "type")]
     pub db_type: DatabaseType,
     pub conn_string: String,
+    /// Overrides the global `query_timeout_secs` for this database
+    #[serde(default)]
+    pub query_timeout_secs: Option<u64>,
+    /// Overrides the global `max_concurrent_queries` for this database
+    #[serde(default)]
+    pub max_concurrent_queries: Option<usize>,
+    /// Reject INSERT/UPDATE/DELETE against this database (the default)
+    #[serde(default = "default_read_only")]
+    pub read_only: bool,
+    /// Row limit used when a request asks for none (default: 500)
+    #[serde(default)]
+    pub default_limit: Option<usize>,
+    /// Cap on the rows a single query may return (default: 5000)
+    #[serde(default)]
+    pub max_limit: Option<usize>,
+    /// TLS mode for Postgres/MySQL, overriding any `sslmode` in `conn_string`
+    #[serde(default)]
+    pub ssl_mode: Option<SslMode>,
+    /// CA certificate (PEM) used to verify the server's certificate
+    #[serde(default)]
+    pub ssl_root_cert: Option<PathBuf>,
+    /// Glob patterns of the only tables that may be browsed or queried
+    #[serde(default)]
+    pub allowed_tables: Option<Vec<String>>,
+    /// Glob patterns of tables hidden from browsing and queries; these win
+    /// over `allowed_tables`
+    #[serde(default)]
+    pub denied_tables: Vec<String>,
+    /// `table.column` glob patterns of columns whose values are replaced
+    /// with `***` in query results; a bare `column` matches any table
+    #[serde(default)]
+    pub masked_columns: Vec<String>,
+    /// Roles allowed to use this database; a caller needs at least one of
+    /// them in its token. Empty allows every authenticated caller.
+    #[serde(default)]
+    pub required_roles: Vec<String>,
+    /// Return Postgres and SQL Server NUMERIC/DECIMAL values as strings, so
+    /// clients that parse JSON numbers as doubles don't lose digits. Off by
+    /// default: they are JSON numbers, parsed as doubles. On Postgres each
+    /// query is prepared once more to find its NUMERIC columns.
+    /// MySQL always returns DECIMAL values as strings.
+    #[serde(default)]
+    pub exact_numeric: bool,
+    /// Prepared statements each Postgres connection keeps for reuse
+    /// (default: 100); 0 re-prepares every query
+    #[serde(default)]
+    pub statement_cache_capacity: Option<usize>,
+}
+
+/// How a Postgres or MySQL connection uses TLS, named after libpq's `sslmode`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SslMode {
+    /// Never use TLS
+    Disable,
+    /// Use TLS if the server supports it (the driver default)
+    Prefer,
+    /// Fail unless the connection is encrypted
+    Require,
+    /// Like `require`, and verify the certificate against the CA
+    VerifyCa,
+    /// Like `verify-ca`, and check the certificate matches the host name
+    VerifyFull,
+}
+
+impl DatabaseConfig {
+    /// Check the settings serde can't, i.e. that the row limits are consistent
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        if self.max_concurrent_queries == Some(0) {
+            bail!(
+                "Database '{}': max_concurrent_queries must be at least 1",
+                self.name
+            );
+        }
+        let limits = RowLimits::new(self);
+        if limits.default > limits.max {
+            bail!(
+                "Database '{}': default_limit ({}) exceeds max_limit ({})",
+                self.name,
+                limits.default,
+                limits.max
+            );
+        }
+        if self.ssl_mode.is_some() || self.ssl_root_cert.is_some() {
+            match self.db_type {
+                DatabaseType::Sqlite => bail!(
+                    "Database '{}': ssl_mode and ssl_root_cert don't apply to SQLite",
+                    self.name
+                ),
+                DatabaseType::Clickhouse => bail!(
+                    "Database '{}': ssl_mode and ssl_root_cert don't apply to ClickHouse; use a clickhouses:// conn_string for TLS",
+                    self.name
+                ),
+                DatabaseType::Mssql => bail!(
+                    "Database '{}': ssl_mode and ssl_root_cert don't apply to SQL Server; set encrypt and TrustServerCertificate in the conn_string",
+                    self.name
+                ),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A user allowed to obtain a JWT via `/api/login`
+#[derive(Serialize, Deserialize, Clone)]
+pub struct UserCredentials {
+    pub username: String,
+    pub password: String,
+    /// Roles put into the tokens issued to this user
+    #[serde(default)]
+    pub roles: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -19,25 +139,347 @@ pub struct AppConfig {
     #[serde(default)] // Provide default empty vec if missing
     pub databases: Vec<DatabaseConfig>,
     pub jwt_secret: String,
-    pub allowed_origin: String,
+    /// Algorithm tokens are signed with; HMAC variants use `jwt_secret`
+    #[serde(default = "default_jwt_algorithm")]
+    pub jwt_algorithm: Algorithm,
+    /// PEM public key used to verify asymmetric (RSA/EC/EdDSA) tokens
+    #[serde(default)]
+    pub jwt_public_key_path: Option<String>,
+    /// Expected `aud` claim; tokens for another audience are rejected
+    #[serde(default)]
+    pub jwt_audience: Option<String>,
+    /// Expected `iss` claim; tokens from another issuer are rejected
+    #[serde(default)]
+    pub jwt_issuer: Option<String>,
+    /// Origins allowed by CORS, as a list or a comma-separated string;
+    /// `"*"` allows any origin
+    #[serde(deserialize_with = "deserialize_origins")]
+    pub allowed_origin: Vec<String>,
+    /// Maximum time a single query may run before it is cancelled
+    #[serde(default)]
+    pub query_timeout_secs: Option<u64>,
+    /// Queries running longer than this are logged as warnings and counted
+    /// in the `r2_slow_queries_total` metric
+    #[serde(default)]
+    pub slow_query_threshold_ms: Option<u64>,
+    /// Queries that may run at once against each database; more are
+    /// rejected with 503 unless a slot frees up shortly
+    #[serde(default = "default_max_concurrent_queries")]
+    pub max_concurrent_queries: usize,
+    /// Credentials accepted by the login endpoint
+    #[serde(default)]
+    pub users: Vec<UserCredentials>,
+    /// Token subjects allowed to use the `/api/admin` endpoints
+    #[serde(default)]
+    pub admins: Vec<String>,
+    /// Lifetime of tokens issued by the login endpoint
+    #[serde(default = "default_token_ttl_secs")]
+    pub token_ttl_secs: u64,
+    /// How long fetched schemas (full and per-table) stay cached
+    #[serde(default = "default_schema_cache_ttl_secs")]
+    pub schema_cache_ttl_secs: u64,
+    /// Databases, and tables within each, whose schemas are fetched at once
+    /// when building the full schema
+    #[serde(default = "default_schema_fetch_concurrency")]
+    pub schema_fetch_concurrency: usize,
+    /// How long query results stay cached for requests that opt in
+    #[serde(default = "default_cache_ttl_secs")]
+    pub cache_ttl_secs: u64,
+    /// Largest accepted request body, in bytes; larger ones get 413
+    #[serde(default = "default_max_query_bytes")]
+    pub max_query_bytes: usize,
+    /// How long in-flight requests may take to finish after a shutdown
+    /// signal before the server exits anyway
+    #[serde(default = "default_shutdown_grace_secs")]
+    pub shutdown_grace_secs: u64,
+    /// Settings for AI query generation
+    #[serde(default)]
+    pub ai: AiConfig,
+    /// Where records of executed and generated queries are written
+    #[serde(default)]
+    pub audit: AuditConfig,
+    /// Where users' saved queries are kept
+    #[serde(default)]
+    pub saved_queries: SavedQueriesConfig,
+}
+
+/// Settings for the audit log of executed and generated queries
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AuditConfig {
+    #[serde(default)]
+    pub sink: AuditSink,
+    /// JSONL file the `file` sink appends to
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+}
+
+/// Where audit records go, one JSON object per line
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditSink {
+    /// Audit logging is disabled
+    #[default]
+    Off,
+    Stdout,
+    /// Append to the file at `path`
+    File,
+}
+
+/// Settings for the store of users' saved queries
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SavedQueriesConfig {
+    #[serde(default)]
+    pub store: SavedQueryStore,
+    /// JSON file the `file` store loads at startup and rewrites on change
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+}
+
+/// Where saved queries are kept
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SavedQueryStore {
+    /// In memory; saved queries are lost when the server restarts
+    #[default]
+    Memory,
+    /// In the JSON file at `path`
+    File,
+}
+
+/// Settings for AI query generation
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AiConfig {
+    /// Whether AI query generation is available; when enabled the provider's
+    /// API key must be set at startup
+    #[serde(default = "default_ai_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub provider: AiProvider,
+    /// Model (or Azure deployment) name
+    #[serde(default = "default_ai_model")]
+    pub model: String,
+    /// OpenAI-compatible API base URL, or the Azure OpenAI endpoint
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// Azure OpenAI API version
+    #[serde(default = "default_ai_api_version")]
+    pub api_version: String,
+    /// Maximum size of the schema text put into the prompt; larger schemas
+    /// are truncated
+    #[serde(default = "default_schema_char_budget")]
+    pub schema_char_budget: usize,
+    /// How many times a failing generated query is sent back to the model
+    /// with the database error for correction
+    #[serde(default = "default_max_repair_attempts")]
+    pub max_repair_attempts: u32,
+}
+
+/// Supported AI providers; the API key is read from `OPENAI_API_KEY` or
+/// `AZURE_API_KEY` respectively
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AiProvider {
+    #[default]
+    OpenAi,
+    Azure,
+}
+
+impl Default for AiConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_ai_enabled(),
+            provider: AiProvider::default(),
+            model: default_ai_model(),
+            base_url: None,
+            api_version: default_ai_api_version(),
+            schema_char_budget: default_schema_char_budget(),
+            max_repair_attempts: default_max_repair_attempts(),
+        }
+    }
+}
+
+fn default_jwt_algorithm() -> Algorithm {
+    Algorithm::HS256
+}
+
+fn default_read_only() -> bool {
+    true
+}
+
+fn default_token_ttl_secs() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_cache_ttl_secs() -> u64 {
+    60
+}
+
+fn default_schema_cache_ttl_secs() -> u64 {
+    10 * 60
+}
+
+fn default_schema_fetch_concurrency() -> usize {
+    8
+}
+
+fn default_max_query_bytes() -> usize {
+    1024 * 1024
+}
+
+fn default_shutdown_grace_secs() -> u64 {
+    30
+}
+
+fn default_max_concurrent_queries() -> usize {
+    10
+}
+
+fn default_ai_enabled() -> bool {
+    true
+}
+
+fn default_ai_model() -> String {
+    "gpt-4o".to_string()
+}
+
+fn default_ai_api_version() -> String {
+    "2024-10-21".to_string()
+}
+
+fn default_max_repair_attempts() -> u32 {
+    2
+}
+
+fn default_schema_char_budget() -> usize {
+    60_000
+}
+
+/// Accept either a list of origins or a single comma-separated string, so
+/// the value can also come from an environment variable
+fn deserialize_origins<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Origins {
+        List(Vec<String>),
+        Csv(String),
+    }
+
+    Ok(match Origins::deserialize(deserializer)? {
+        Origins::List(origins) => origins,
+        Origins::Csv(origins) => origins
+            .split(',')
+            .map(str::trim)
+            .filter(|origin| !origin.is_empty())
+            .map(str::to_string)
+            .collect(),
+    })
+}
+
+// Manual Debug implementation so passwords never end up in logs
+impl fmt::Debug for UserCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UserCredentials")
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
 }
 
+/// Fields that have no default and must come from a file or the environment
+const REQUIRED_FIELDS: [&str; 3] = ["server_addr", "jwt_secret", "allowed_origin"];
+
+/// Environment variable holding the database list as a JSON array
+const DATABASES_ENV: &str = "APP__DATABASES";
+
 impl AppConfig {
+    /// Load `default` and `development` config files from `config_path`,
+    /// overridden by `APP__*` environment variables. The files are optional,
+    /// so the whole configuration can come from the environment.
     pub fn load(config_path: &str) -> Result<Self, anyhow::Error> {
+        Self::load_with_env(config_path, std::env::vars().collect())
+    }
+
+    fn load_with_env(
+        config_path: &str,
+        env: HashMap<String, String>,
+    ) -> Result<Self, anyhow::Error> {
         // Construct paths for configuration files
         let default_config = Path::new(config_path).join("default");
         let dev_config = Path::new(config_path).join("development");
 
         // Load configuration
-        let config = Config::builder()
-            .add_source(File::with_name(default_config.to_str().unwrap()))
+        let mut builder = Config::builder()
+            .add_source(File::with_name(default_config.to_str().unwrap()).required(false))
             .add_source(File::with_name(dev_config.to_str().unwrap()).required(false))
-            .add_source(Environment::with_prefix("APP").separator("__"))
-            .build()?;
+            .add_source(
+                Environment::with_prefix("APP")
+                    .separator("__")
+                    .source(Some(env.clone())),
+            );
+        // A flat env var can't describe a list of tables, so accept JSON
+        if let Some(databases) = env.get(DATABASES_ENV) {
+            serde_json::from_str::<Vec<DatabaseConfig>>(databases)
+                .with_context(|| format!("{} is not a valid JSON database list", DATABASES_ENV))?;
+            builder = builder.add_source(File::from_str(
+                &format!(r#"{{"databases": {}}}"#, databases),
+                FileFormat::Json,
+            ));
+        }
+        let config = builder.build()?;
+
+        let missing: Vec<&str> = REQUIRED_FIELDS
+            .into_iter()
+            .filter(|field| config.get::<config::Value>(field).is_err())
+            .collect();
+        if !missing.is_empty() {
+            bail!(
+                "Missing required configuration: {} (set them in {}/default.toml or as {})",
+                missing.join(", "),
+                config_path,
+                missing
+                    .iter()
+                    .map(|field| format!("APP__{}", field.to_uppercase()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
 
         let app_config: AppConfig = config.try_deserialize()?;
+        for db_config in &app_config.databases {
+            db_config.validate()?;
+        }
+        if app_config.max_concurrent_queries == 0 {
+            bail!("max_concurrent_queries must be at least 1");
+        }
+        if app_config.audit.sink == AuditSink::File && app_config.audit.path.is_none() {
+            bail!("audit.path is required for the file audit sink");
+        }
+        if app_config.saved_queries.store == SavedQueryStore::File
+            && app_config.saved_queries.path.is_none()
+        {
+            bail!("saved_queries.path is required for the file saved query store");
+        }
+        app_config.cors_origins()?;
         Ok(app_config)
     }
+
+    /// Allowed CORS origins as header values, or `None` if any origin is
+    /// allowed
+    pub fn cors_origins(&self) -> Result<Option<Vec<HeaderValue>>, anyhow::Error> {
+        if self.allowed_origin.iter().any(|origin| origin == "*") {
+            return Ok(None);
+        }
+        self.allowed_origin
+            .iter()
+            .map(|origin| {
+                HeaderValue::from_str(origin)
+                    .with_context(|| format!("Invalid allowed_origin entry: {:?}", origin))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Some)
+    }
 }
 
 impl fmt::Display for DatabaseType {
@@ -45,6 +487,9 @@ impl fmt::Display for DatabaseType {
         match self {
             DatabaseType::Postgres => write!(f, "postgres"),
             DatabaseType::Mysql => write!(f, "mysql"),
+            DatabaseType::Sqlite => write!(f, "sqlite"),
+            DatabaseType::Clickhouse => write!(f, "clickhouse"),
+            DatabaseType::Mssql => write!(f, "mssql"),
         }
     }
 }
@@ -56,7 +501,150 @@ impl FromStr for DatabaseType {
         match s.to_lowercase().as_str() {
             "postgres" | "postgresql" => Ok(DatabaseType::Postgres),
             "mysql" | "mariadb" => Ok(DatabaseType::Mysql),
+            "sqlite" | "sqlite3" => Ok(DatabaseType::Sqlite),
+            "clickhouse" => Ok(DatabaseType::Clickhouse),
+            "mssql" | "sqlserver" => Ok(DatabaseType::Mssql),
             _ => Err(anyhow::anyhow!("Invalid database type: {}", s)),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> HashMap<String, String> {
+        vars.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_load_from_env_only() {
+        let config = AppConfig::load_with_env(
+            "./no-such-dir",
+            env(&[
+                ("APP__SERVER_ADDR", "0.0.0.0:3111"),
+                ("APP__JWT_SECRET", "secret"),
+                ("APP__ALLOWED_ORIGIN", "http://localhost:5173"),
+                (
+                    "APP__DATABASES",
+                    r#"[{"name": "users", "type": "postgres", "conn_string": "postgres://localhost/users", "read_only": false}]"#,
+                ),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(config.server_addr, "0.0.0.0:3111");
+        assert_eq!(config.databases.len(), 1);
+        assert_eq!(config.databases[0].db_type, DatabaseType::Postgres);
+        assert!(!config.databases[0].read_only);
+    }
+
+    #[test]
+    fn test_database_ssl_settings() {
+        let load = |database: &str| {
+            AppConfig::load_with_env(
+                "./no-such-dir",
+                env(&[
+                    ("APP__SERVER_ADDR", "0.0.0.0:3111"),
+                    ("APP__JWT_SECRET", "secret"),
+                    ("APP__ALLOWED_ORIGIN", "*"),
+                    ("APP__DATABASES", &format!("[{}]", database)),
+                ]),
+            )
+        };
+
+        let config = load(
+            r#"{"name": "facts", "type": "postgres", "conn_string": "postgres://db.example.com/facts", "ssl_mode": "verify-full", "ssl_root_cert": "/etc/ssl/ca.pem"}"#,
+        )
+        .unwrap();
+        let db = &config.databases[0];
+        assert_eq!(db.ssl_mode, Some(SslMode::VerifyFull));
+        assert_eq!(db.ssl_root_cert, Some(PathBuf::from("/etc/ssl/ca.pem")));
+
+        let err = load(
+            r#"{"name": "local", "type": "sqlite", "conn_string": "sqlite::memory:", "ssl_mode": "require"}"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("don't apply to SQLite"));
+    }
+
+    #[test]
+    fn test_database_limits_are_validated() {
+        let load = |database: &str| {
+            AppConfig::load_with_env(
+                "./no-such-dir",
+                env(&[
+                    ("APP__SERVER_ADDR", "0.0.0.0:3111"),
+                    ("APP__JWT_SECRET", "secret"),
+                    ("APP__ALLOWED_ORIGIN", "*"),
+                    ("APP__DATABASES", &format!("[{}]", database)),
+                ]),
+            )
+        };
+
+        let config = load(
+            r#"{"name": "facts", "type": "postgres", "conn_string": "postgres://localhost/facts", "max_limit": 100}"#,
+        )
+        .unwrap();
+        let limits = RowLimits::new(&config.databases[0]);
+        assert_eq!((limits.default, limits.max), (100, 100));
+
+        let err = load(
+            r#"{"name": "facts", "type": "postgres", "conn_string": "postgres://localhost/facts", "default_limit": 200, "max_limit": 100}"#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Database 'facts': default_limit (200) exceeds max_limit (100)"
+        );
+    }
+
+    #[test]
+    fn test_allowed_origins() {
+        let load = |origins: &str| {
+            AppConfig::load_with_env(
+                "./no-such-dir",
+                env(&[
+                    ("APP__SERVER_ADDR", "0.0.0.0:3111"),
+                    ("APP__JWT_SECRET", "secret"),
+                    ("APP__ALLOWED_ORIGIN", origins),
+                ]),
+            )
+        };
+
+        let config = load("http://a.example.com, http://b.example.com").unwrap();
+        assert_eq!(
+            config.allowed_origin,
+            ["http://a.example.com", "http://b.example.com"]
+        );
+        assert_eq!(config.cors_origins().unwrap().unwrap().len(), 2);
+
+        let config = load("*").unwrap();
+        assert!(config.cors_origins().unwrap().is_none());
+
+        let err = load("http://ok.example.com,bad\norigin").unwrap_err();
+        assert!(err.to_string().contains("Invalid allowed_origin entry"));
+    }
+
+    #[test]
+    fn test_load_reports_missing_fields() {
+        let err = AppConfig::load_with_env(
+            "./no-such-dir",
+            env(&[("APP__SERVER_ADDR", "0.0.0.0:3111")]),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Missing required configuration: jwt_secret, allowed_origin \
+             (set them in ./no-such-dir/default.toml or as APP__JWT_SECRET, APP__ALLOWED_ORIGIN)"
+        );
+    }
+
+    #[test]
+    fn test_load_rejects_invalid_databases_json() {
+        let err = AppConfig::load_with_env("./no-such-dir", env(&[("APP__DATABASES", "not json")]))
+            .unwrap_err();
+        assert!(err.to_string().contains("APP__DATABASES"));
+    }
+}
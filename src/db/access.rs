@@ -0,0 +1,718 @@
+use std::ops::ControlFlow;
+
+use serde_json::Value;
+use sqlparser::{
+    ast::{
+        self, Expr, ObjectName, Query, SelectItem, SetExpr, TableFactor, Visit, Visitor,
+        visit_expressions, visit_expressions_mut,
+    },
+    dialect::GenericDialect,
+    parser::Parser,
+};
+use wildmatch::WildMatch;
+
+use super::{TableFilter, TableInfo};
+use crate::{config::DatabaseConfig, error::AppError};
+
+/// Which tables of a database may be browsed and queried, from the
+/// `allowed_tables` and `denied_tables` glob patterns of its config. Names
+/// match case-insensitively, and a name also matches by its last part, so
+/// `billing` covers `public.billing`. It also holds the `masked_columns`
+/// whose values are hidden from query results (see `column_mask`).
+#[derive(Debug, Clone, Default)]
+pub struct TableAccess {
+    allowed: Option<Vec<WildMatch>>, // None allows every table not denied
+    denied: Vec<WildMatch>,
+    masked: Vec<(WildMatch, WildMatch)>, // (table, column) patterns
+}
+
+/// What a masked column's values are replaced with
+pub const MASK: &str = "***";
+
+impl TableAccess {
+    pub fn new(db_config: &DatabaseConfig) -> Self {
+        let patterns = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|p| WildMatch::new_case_insensitive(p))
+                .collect::<Vec<_>>()
+        };
+        // `users.ssn`, `public.users.ssn`, or `ssn` for any table
+        let masked = db_config
+            .masked_columns
+            .iter()
+            .map(|pattern| {
+                let (table, column) = pattern.rsplit_once('.').unwrap_or(("*", pattern));
+                (
+                    WildMatch::new_case_insensitive(table),
+                    WildMatch::new_case_insensitive(column),
+                )
+            })
+            .collect();
+        TableAccess {
+            allowed: db_config.allowed_tables.as_deref().map(patterns),
+            denied: patterns(&db_config.denied_tables),
+            masked,
+        }
+    }
+
+    /// Whether every table is accessible
+    pub fn is_unrestricted(&self) -> bool {
+        self.allowed.is_none() && self.denied.is_empty()
+    }
+
+    /// Whether a table, as named by `list_tables` or in a query, is accessible
+    pub fn is_allowed(&self, table_name: &str) -> bool {
+        let matches = |pattern: &WildMatch| matches_table(pattern, table_name);
+        self.allowed
+            .as_ref()
+            .is_none_or(|allowed| allowed.iter().any(matches))
+            && !self.denied.iter().any(matches)
+    }
+
+    /// The mask for the results of `query`: the masked columns of the
+    /// tables it references, whether selected by name or with `*`, plus
+    /// the aliases of select items computed from them. A query that does
+    /// not parse gets every masked column, whatever its table.
+    pub fn column_mask(&self, query: &str) -> ColumnMask {
+        if self.masked.is_empty() {
+            return ColumnMask::default();
+        }
+        let Ok(statements) = Parser::parse_sql(&GenericDialect {}, query) else {
+            return ColumnMask {
+                columns: self
+                    .masked
+                    .iter()
+                    .map(|(_, column)| column.clone())
+                    .collect(),
+            };
+        };
+        let tables: Vec<_> = statements.iter().flat_map(referenced_tables).collect();
+        let mut columns = self.masked_columns(&tables);
+        if !columns.is_empty() {
+            let mut aliases = MaskedAliases::new(&columns);
+            for stmt in &statements {
+                let _ = stmt.visit(&mut aliases);
+            }
+            let aliases = aliases.aliases;
+            columns.extend(
+                aliases
+                    .iter()
+                    .map(|alias| WildMatch::new_case_insensitive(alias)),
+            );
+        }
+        ColumnMask { columns }
+    }
+
+    /// The masked column patterns of the given tables
+    fn masked_columns(&self, tables: &[String]) -> Vec<WildMatch> {
+        self.masked
+            .iter()
+            .filter(|(table, _)| tables.iter().any(|name| matches_table(table, name)))
+            .map(|(_, column)| column.clone())
+            .collect()
+    }
+
+    /// The query as it may be logged: when it touches masked columns its
+    /// literals, which could hold masked values (`WHERE ssn = '...'`), are
+    /// replaced with `MASK`. Bind placeholders are kept.
+    pub fn redact_query(&self, query: &str) -> String {
+        if self.column_mask(query).is_empty() {
+            return query.to_string();
+        }
+        let Ok(mut statements) = Parser::parse_sql(&GenericDialect {}, query) else {
+            return MASK.to_string();
+        };
+        for stmt in &mut statements {
+            let _ = visit_expressions_mut(stmt, |expr| {
+                if let Expr::Value(value) = expr
+                    && !matches!(value.value, ast::Value::Placeholder(_))
+                {
+                    *expr = Expr::value(ast::Value::SingleQuotedString(MASK.to_string()));
+                }
+                ControlFlow::<()>::Continue(())
+            });
+        }
+        statements
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    /// Fail with `NotFound` unless the table is accessible, so that denied
+    /// tables look no different from missing ones
+    pub fn check_table(&self, table_name: &str) -> Result<(), AppError> {
+        if self.is_allowed(table_name) {
+            Ok(())
+        } else {
+            Err(table_not_found(table_name))
+        }
+    }
+
+    /// Reject a statement that references an inaccessible table anywhere:
+    /// in JOINs, subqueries or CTE bodies (see `referenced_tables`), or that
+    /// could return masked values the mask can't find (see `check_masked`)
+    pub fn check_statement(&self, stmt: &ast::Statement) -> Result<(), AppError> {
+        self.check_masked(stmt)?;
+        if self.is_unrestricted() {
+            return Ok(());
+        }
+        match referenced_tables(stmt)
+            .into_iter()
+            .find(|name| !self.is_allowed(name))
+        {
+            Some(name) => Err(AppError::BadRequest(format!(
+                "Query references table '{}', which is not accessible",
+                name
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// `ColumnMask` masks result columns by name, so reject what returns
+    /// masked values under a name it can't know: a select item computed
+    /// from a masked column without an alias, e.g. `SELECT upper(ssn)`,
+    /// which each backend names differently, and a whole row, e.g. `SELECT
+    /// u` or `row_to_json(u)`, which nests the masked columns in one value
+    fn check_masked(&self, stmt: &ast::Statement) -> Result<(), AppError> {
+        let columns = self.masked_columns(&referenced_tables(stmt));
+        if columns.is_empty() {
+            return Ok(());
+        }
+        let mut aliases = MaskedAliases::new(&columns);
+        let _ = stmt.visit(&mut aliases);
+        if let Some(item) = aliases.unnamed {
+            return Err(AppError::BadRequest(format!(
+                "Select item '{}' reads a masked column and needs an alias",
+                item
+            )));
+        }
+
+        // Any relation in scope could hide a masked table, e.g. a CTE
+        let mut relations = Vec::new();
+        let _ = ast::visit_relations(stmt, |name| {
+            if let Some(part) = name.0.last().and_then(|part| part.as_ident()) {
+                relations.push(part.value.clone());
+            }
+            ControlFlow::<()>::Continue(())
+        });
+        let _ = stmt.visit(&mut RelationAliases(&mut relations));
+        let whole_row = visit_expressions(stmt, |expr| match expr {
+            Expr::Identifier(ident)
+                if relations
+                    .iter()
+                    .any(|name| name.eq_ignore_ascii_case(&ident.value)) =>
+            {
+                ControlFlow::Break(ident.value.clone())
+            }
+            _ => ControlFlow::Continue(()),
+        });
+        match whole_row {
+            ControlFlow::Break(name) => Err(AppError::BadRequest(format!(
+                "Query reads whole rows of '{}' along with masked columns; select columns instead",
+                name
+            ))),
+            ControlFlow::Continue(()) => Ok(()),
+        }
+    }
+
+    /// Drop the inaccessible tables from an unpaged listing, then apply the
+    /// filter's paging, so pages only count accessible tables
+    pub fn filter_tables(&self, tables: Vec<TableInfo>, filter: &TableFilter) -> Vec<TableInfo> {
+        tables
+            .into_iter()
+            .filter(|table| self.is_allowed(&table.name))
+            .skip(filter.offset.unwrap_or(0))
+            .take(filter.limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+}
+
+/// Every table a statement reads or writes, as written and in order of
+/// appearance. References to a CTE in scope are not tables and are left
+/// out; a CTE body only sees the CTEs before it, or all of them in a
+/// `WITH RECURSIVE`.
+pub fn referenced_tables(stmt: &ast::Statement) -> Vec<String> {
+    let mut collector = TableCollector::default();
+    let _ = stmt.visit(&mut collector);
+    collector.tables
+}
+
+#[derive(Default)]
+struct TableCollector {
+    tables: Vec<String>,
+    scopes: Vec<CteScope>, // One per query being visited, innermost last
+}
+
+// The CTEs of one query and how many of them are visible at this point
+struct CteScope {
+    names: Vec<String>,
+    bodies: Vec<*const Query>, // To tell when a visited query is a CTE body
+    visible: usize,
+    recursive: bool,
+}
+
+impl TableCollector {
+    fn is_cte(&self, name: &str) -> bool {
+        self.scopes.iter().any(|scope| {
+            scope.names[..scope.visible]
+                .iter()
+                .any(|cte| cte.eq_ignore_ascii_case(name))
+        })
+    }
+
+    /// Index of the CTE whose body `query` is, in the innermost scope
+    fn cte_body_index(&self, query: &Query) -> Option<usize> {
+        let scope = self.scopes.last()?;
+        scope
+            .bodies
+            .iter()
+            .position(|&body| std::ptr::eq(body, query))
+    }
+}
+
+impl Visitor for TableCollector {
+    type Break = ();
+
+    fn pre_visit_query(&mut self, query: &Query) -> ControlFlow<()> {
+        // CTE bodies are visited first: narrow the enclosing scope to the
+        // CTEs this body may reference
+        if let Some(index) = self.cte_body_index(query) {
+            let scope = self.scopes.last_mut().unwrap();
+            if !scope.recursive {
+                scope.visible = index;
+            }
+        }
+        let (names, bodies, recursive) = match &query.with {
+            Some(with) => (
+                with.cte_tables
+                    .iter()
+                    .map(|cte| cte.alias.name.value.clone())
+                    .collect(),
+                with.cte_tables
+                    .iter()
+                    .map(|cte| &*cte.query as *const Query)
+                    .collect(),
+                with.recursive,
+            ),
+            None => (Vec::new(), Vec::new(), false),
+        };
+        let visible = if recursive { names.len() } else { 0 };
+        self.scopes.push(CteScope {
+            names,
+            bodies,
+            visible,
+            recursive,
+        });
+        ControlFlow::Continue(())
+    }
+
+    fn post_visit_query(&mut self, query: &Query) -> ControlFlow<()> {
+        self.scopes.pop();
+        // Whatever follows a CTE body may reference that CTE too
+        if let Some(index) = self.cte_body_index(query) {
+            let scope = self.scopes.last_mut().unwrap();
+            scope.visible = scope.visible.max(index + 1);
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_relation(&mut self, relation: &ObjectName) -> ControlFlow<()> {
+        let name = object_name(relation);
+        // Only an unqualified name can refer to a CTE
+        if relation.0.len() > 1 || !self.is_cte(&name) {
+            self.tables.push(name);
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Column names whose values are replaced with `MASK` in query results
+#[derive(Debug, Clone, Default)]
+pub struct ColumnMask {
+    columns: Vec<WildMatch>,
+}
+
+impl ColumnMask {
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    /// Mask the matching columns of a row, or of every row in an array of
+    /// rows. Works on the JSON rows of any backend.
+    pub fn apply(&self, data: &mut Value) {
+        if self.columns.is_empty() {
+            return;
+        }
+        match data {
+            Value::Array(rows) => rows.iter_mut().for_each(|row| self.apply(row)),
+            Value::Object(row) => {
+                for (column, value) in row.iter_mut() {
+                    if self.columns.iter().any(|pattern| pattern.matches(column)) {
+                        *value = Value::String(MASK.to_string());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Collects the aliases of select items whose expression reads a masked
+// column, e.g. `s` in `SELECT upper(ssn) AS s`, and the first such item
+// without an alias. Columns of a set operation are named by its left side,
+// so `SELECT name ... UNION SELECT ssn ...` masks `name`.
+struct MaskedAliases<'a> {
+    columns: &'a [WildMatch],
+    aliases: Vec<String>,
+    unnamed: Option<String>,
+}
+
+impl<'a> MaskedAliases<'a> {
+    fn new(columns: &'a [WildMatch]) -> Self {
+        MaskedAliases {
+            columns,
+            aliases: Vec::new(),
+            unnamed: None,
+        }
+    }
+
+    fn collect(&mut self, body: &SetExpr) {
+        match body {
+            SetExpr::Select(select) => {
+                for item in &select.projection {
+                    match item {
+                        SelectItem::ExprWithAlias { expr, alias } if self.reads_masked(expr) => {
+                            self.aliases.push(alias.value.clone());
+                        }
+                        // A bare column keeps its name, which the mask covers
+                        SelectItem::UnnamedExpr(
+                            Expr::Identifier(_) | Expr::CompoundIdentifier(_),
+                        ) => {}
+                        SelectItem::UnnamedExpr(expr) if self.reads_masked(expr) => {
+                            self.unnamed.get_or_insert_with(|| expr.to_string());
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            SetExpr::SetOperation { left, right, .. } => {
+                self.collect(left);
+                self.collect(right);
+                if let (Some(names), Some(items)) = (projection(left), projection(right)) {
+                    for (name, item) in names.iter().zip(items) {
+                        if let SelectItem::UnnamedExpr(expr)
+                        | SelectItem::ExprWithAlias { expr, .. } = item
+                            && self.reads_masked(expr)
+                            && let Some(name) = item_name(name)
+                        {
+                            self.aliases.push(name);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn reads_masked(&self, expr: &Expr) -> bool {
+        let is_masked = |column: &str| self.columns.iter().any(|p| p.matches(column));
+        visit_expressions(expr, |expr| match expr {
+            Expr::Identifier(ident) if is_masked(&ident.value) => ControlFlow::Break(()),
+            Expr::CompoundIdentifier(idents)
+                if idents.last().is_some_and(|ident| is_masked(&ident.value)) =>
+            {
+                ControlFlow::Break(())
+            }
+            _ => ControlFlow::Continue(()),
+        })
+        .is_break()
+    }
+}
+
+impl Visitor for MaskedAliases<'_> {
+    type Break = ();
+
+    fn pre_visit_query(&mut self, query: &Query) -> ControlFlow<()> {
+        self.collect(&query.body);
+        ControlFlow::Continue(())
+    }
+}
+
+// Collects the aliases of the relations in a statement, e.g. `u` in
+// `FROM users u` or `FROM (SELECT ...) u`
+struct RelationAliases<'a>(&'a mut Vec<String>);
+
+impl Visitor for RelationAliases<'_> {
+    type Break = ();
+
+    fn pre_visit_table_factor(&mut self, factor: &TableFactor) -> ControlFlow<()> {
+        if let TableFactor::Table {
+            alias: Some(alias), ..
+        }
+        | TableFactor::Derived {
+            alias: Some(alias), ..
+        }
+        | TableFactor::Function {
+            alias: Some(alias), ..
+        }
+        | TableFactor::UNNEST {
+            alias: Some(alias), ..
+        } = factor
+        {
+            self.0.push(alias.name.value.clone());
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// The select items naming the columns of a query body: those of its
+/// leftmost SELECT
+fn projection(body: &SetExpr) -> Option<&[SelectItem]> {
+    match body {
+        SetExpr::Select(select) => Some(&select.projection),
+        SetExpr::SetOperation { left, .. } => projection(left),
+        SetExpr::Query(query) => projection(&query.body),
+        _ => None,
+    }
+}
+
+/// The name of the column a select item returns, when it is plain
+fn item_name(item: &SelectItem) -> Option<String> {
+    match item {
+        SelectItem::ExprWithAlias { alias, .. } => Some(alias.value.clone()),
+        SelectItem::UnnamedExpr(Expr::Identifier(ident)) => Some(ident.value.clone()),
+        SelectItem::UnnamedExpr(Expr::CompoundIdentifier(idents)) => {
+            idents.last().map(|ident| ident.value.clone())
+        }
+        _ => None,
+    }
+}
+
+/// Whether a table name matches a pattern, in full or by its last part
+fn matches_table(pattern: &WildMatch, table_name: &str) -> bool {
+    let unqualified = table_name.rsplit('.').next().unwrap_or(table_name);
+    pattern.matches(table_name) || pattern.matches(unqualified)
+}
+
+/// The name as written, without quotes, e.g. `public.users`
+fn object_name(name: &ObjectName) -> String {
+    name.0
+        .iter()
+        .map(|part| match part.as_ident() {
+            Some(ident) => ident.value.clone(),
+            None => part.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+fn table_not_found(table_name: &str) -> AppError {
+    AppError::NotFound(format!("Table '{}' not found", table_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::TableType;
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn config(allowed: Option<&[&str]>, denied: &[&str]) -> DatabaseConfig {
+        DatabaseConfig {
+            name: "test".to_string(),
+            db_type: crate::DatabaseType::Postgres,
+            conn_string: String::new(),
+            query_timeout_secs: None,
+            read_only: true,
+            default_limit: None,
+            max_limit: None,
+            ssl_mode: None,
+            ssl_root_cert: None,
+            allowed_tables: allowed.map(patterns),
+            denied_tables: patterns(denied),
+            masked_columns: Vec::new(),
+            required_roles: Vec::new(),
+            exact_numeric: false,
+            statement_cache_capacity: None,
+            max_concurrent_queries: None,
+        }
+    }
+
+    fn access(allowed: Option<&[&str]>, denied: &[&str]) -> TableAccess {
+        TableAccess::new(&config(allowed, denied))
+    }
+
+    fn masked(columns: &[&str]) -> TableAccess {
+        TableAccess::new(&DatabaseConfig {
+            masked_columns: patterns(columns),
+            ..config(None, &[])
+        })
+    }
+
+    fn check(access: &TableAccess, sql: &str) -> Result<(), AppError> {
+        let stmt = Parser::parse_sql(&GenericDialect {}, sql)
+            .unwrap()
+            .remove(0);
+        access.check_statement(&stmt)
+    }
+
+    #[test]
+    fn test_is_allowed() {
+        let access = access(
+            Some(&["public.*", "audit_log"]),
+            &["*credentials", "billing"],
+        );
+        assert!(access.is_allowed("public.users"));
+        assert!(access.is_allowed("PUBLIC.Users"));
+        assert!(access.is_allowed("reports.audit_log"));
+        assert!(!access.is_allowed("reports.summary"));
+        assert!(!access.is_allowed("public.billing"));
+        assert!(!access.is_allowed("public.user_credentials"));
+    }
+
+    #[test]
+    fn test_filter_tables() {
+        let tables = [
+            "public.billing",
+            "public.orders",
+            "public.posts",
+            "public.users",
+        ]
+        .map(|name| TableInfo {
+            name: name.to_string(),
+            table_type: TableType::Table,
+            row_estimate: None,
+            description: None,
+        })
+        .to_vec();
+        let filter = TableFilter {
+            limit: Some(2),
+            offset: Some(1),
+            ..Default::default()
+        };
+        let names: Vec<_> = access(None, &["billing"])
+            .filter_tables(tables, &filter)
+            .into_iter()
+            .map(|table| table.name)
+            .collect();
+        assert_eq!(names, ["public.posts", "public.users"]);
+    }
+
+    #[test]
+    fn test_check_statement() {
+        let access = access(None, &["billing"]);
+        assert!(check(&access, "SELECT * FROM users").is_ok());
+        let err = check(&access, r#"SELECT * FROM public."billing""#).unwrap_err();
+        assert!(matches!(err, AppError::BadRequest(msg) if msg.contains("'public.billing'")));
+        assert!(
+            check(
+                &access,
+                "SELECT * FROM users u JOIN billing b ON b.id = u.id"
+            )
+            .is_err()
+        );
+        assert!(
+            check(
+                &access,
+                "SELECT * FROM users WHERE id IN (SELECT id FROM billing)"
+            )
+            .is_err()
+        );
+        assert!(check(&access, "DELETE FROM billing").is_err());
+        // A CTE may shadow a denied name, but not read the table itself
+        assert!(
+            check(
+                &access,
+                "WITH billing AS (SELECT 1 AS n) SELECT * FROM billing"
+            )
+            .is_ok()
+        );
+        assert!(
+            check(
+                &access,
+                "WITH billing AS (SELECT * FROM billing) SELECT * FROM billing"
+            )
+            .is_err()
+        );
+        assert!(check(&access, "WITH b AS (SELECT * FROM billing) SELECT * FROM b").is_err());
+    }
+
+    #[test]
+    fn test_referenced_tables() {
+        let tables = |sql: &str| {
+            let stmt = Parser::parse_sql(&GenericDialect {}, sql)
+                .unwrap()
+                .remove(0);
+            referenced_tables(&stmt)
+        };
+        assert_eq!(
+            tables(
+                "SELECT * FROM a JOIN s.b ON a.id = b.id \
+                 WHERE a.x IN (SELECT x FROM (SELECT x FROM c) AS t) \
+                 AND EXISTS (SELECT 1 FROM d WHERE d.id = a.id)"
+            ),
+            ["a", "s.b", "c", "d"]
+        );
+        // CTEs are visible to the main query and to later CTEs only
+        assert_eq!(
+            tables("WITH x AS (SELECT * FROM y), y AS (SELECT * FROM x) SELECT * FROM x, y, z"),
+            ["y", "z"]
+        );
+        assert!(
+            tables("WITH RECURSIVE r AS (SELECT 1 UNION ALL SELECT * FROM r) SELECT * FROM r")
+                .is_empty()
+        );
+        // A CTE in a subquery is not in scope outside it
+        assert_eq!(
+            tables("SELECT * FROM (WITH t AS (SELECT 1) SELECT * FROM t) AS s, t"),
+            ["t"]
+        );
+    }
+
+    #[test]
+    fn test_redact_query() {
+        let access = masked(&["users.ssn"]);
+        assert_eq!(
+            access.redact_query("SELECT name FROM users WHERE ssn = '123-45-6789' AND id = $1"),
+            "SELECT name FROM users WHERE ssn = '***' AND id = $1"
+        );
+        // Queries that can't see a masked column are logged as they are
+        let query = "SELECT * FROM posts WHERE title = 'hello'";
+        assert_eq!(access.redact_query(query), query);
+        assert_eq!(access.redact_query("SELEC ssn FROM users"), MASK);
+    }
+
+    #[test]
+    fn test_check_masked() {
+        let access = masked(&["users.ssn"]);
+        for sql in [
+            "SELECT ssn, u.ssn, upper(ssn) AS s FROM users u",
+            "SELECT upper(ssn) FROM posts",
+            "SELECT u.* FROM users u",
+            "SELECT row_to_json(p) FROM posts p",
+        ] {
+            assert!(check(&access, sql).is_ok(), "{}", sql);
+        }
+        for sql in [
+            "SELECT upper(ssn) FROM users",
+            "SELECT id FROM (SELECT id, ssn || '' FROM users) s",
+            "SELECT u FROM users u",
+            "SELECT row_to_json(u) AS j FROM users u",
+            "SELECT row_to_json(users) FROM users",
+            "WITH c AS (SELECT * FROM users) SELECT to_json(c) AS j FROM c",
+        ] {
+            let err = check(&access, sql).unwrap_err();
+            assert!(matches!(err, AppError::BadRequest(_)), "{}", sql);
+        }
+
+        // A set operation's columns are named by its left side
+        let mask = access.column_mask("SELECT name FROM posts UNION SELECT ssn FROM users");
+        let mut row = serde_json::json!({ "name": "123-45-6789" });
+        mask.apply(&mut row);
+        assert_eq!(row["name"], MASK);
+    }
+}
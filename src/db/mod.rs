@@ -1,136 +1,914 @@
+mod access;
+mod clickhouse;
+mod mssql;
 mod mysql;
 mod pg;
+mod sqlite;
+
+pub use access::TableAccess;
+pub use pg::pg_notice_layer;
 
 use crate::{config::DatabaseConfig, error::AppError};
+use clickhouse::ClickhouseClient;
+use futures::stream::BoxStream;
+use mssql::MssqlPool;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use sqlparser::{ast, dialect::GenericDialect, parser::Parser};
-use sqlx::{MySqlPool, PgPool};
-use std::{cmp::min, convert::Infallible, str::FromStr, time::Duration};
+use serde_json::{Map, Value};
+use sqlparser::{
+    ast,
+    dialect::{Dialect, GenericDialect},
+    keywords::Keyword,
+    parser::{Parser, ParserError},
+    tokenizer::{Location, Token, Tokenizer},
+};
+use sqlx::{Arguments, Database, Encode, MySqlPool, PgPool, SqlitePool, Type, types::Json};
+use std::{
+    cmp::{max, min},
+    collections::HashMap,
+    convert::Infallible,
+    ops::ControlFlow,
+    str::FromStr,
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+use tracing::{Span, field, instrument};
 
 const DEFAULT_LIMIT: usize = 500;
 const MAX_LIMIT: usize = 5000;
+/// Longest SQL text accepted by the sanitizers, in bytes
+const MAX_QUERY_LEN: usize = 256 * 1024;
+
+tokio::task_local! {
+    /// Set while a cancellable query or one with a timeout runs; backends
+    /// that can cancel a query server-side record the process id of the
+    /// connection running it
+    pub static BACKEND_PID: Arc<OnceLock<i32>>;
+}
+
+/// Longest query text recorded in tracing spans
+const MAX_LOGGED_QUERY_LEN: usize = 200;
+
+/// Shorten a query for logging, cutting on a char boundary
+pub fn truncate_for_log(query: &str) -> String {
+    if query.len() <= MAX_LOGGED_QUERY_LEN {
+        return query.to_string();
+    }
+    let mut end = MAX_LOGGED_QUERY_LEN;
+    while !query.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}...", &query[..end])
+}
+
+/// Row limits applied to the queries of one database
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RowLimits {
+    /// Limit used when the request asks for none
+    pub default: usize,
+    /// Cap on requested and explicit `LIMIT`s
+    pub max: usize,
+}
+
+impl Default for RowLimits {
+    fn default() -> Self {
+        RowLimits {
+            default: DEFAULT_LIMIT,
+            max: MAX_LIMIT,
+        }
+    }
+}
+
+impl RowLimits {
+    /// Limits of a database, falling back to the global ones. Without an
+    /// explicit `default_limit`, the default never exceeds the max.
+    pub fn new(db_config: &DatabaseConfig) -> Self {
+        let max = db_config.max_limit.unwrap_or(MAX_LIMIT);
+        RowLimits {
+            default: db_config.default_limit.unwrap_or(min(DEFAULT_LIMIT, max)),
+            max,
+        }
+    }
+
+    /// Row limit actually applied to a query given the requested one
+    pub fn effective(&self, limit: Option<usize>) -> usize {
+        min(limit.unwrap_or(self.default), self.max)
+    }
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 #[non_exhaustive]
 #[serde(rename_all = "lowercase")]
 pub enum DatabaseType {
     Postgres,
     Mysql,
+    Sqlite,
+    Clickhouse,
+    #[serde(alias = "sqlserver")]
+    Mssql,
 }
 
+/// The last field is the database's `exact_numeric` setting
 #[derive(Debug)]
-pub struct PgPoolHandler(PgPool);
+pub struct PgPoolHandler(PgPool, RowLimits, TableAccess, bool);
 
 #[derive(Debug)]
-pub struct MySqlPoolHandler(MySqlPool);
+pub struct MySqlPoolHandler(MySqlPool, RowLimits, TableAccess);
+
+#[derive(Debug)]
+pub struct SqlitePoolHandler(SqlitePool, RowLimits, TableAccess);
+
+#[derive(Debug)]
+pub struct ClickhousePoolHandler(ClickhouseClient, RowLimits, TableAccess);
+
+/// The last field is the database's `exact_numeric` setting
+#[derive(Debug)]
+pub struct MssqlPoolHandler(MssqlPool, RowLimits, TableAccess, bool);
 
 #[derive(Debug)]
 pub enum DbPool {
     Postgres(PgPoolHandler),
     MySql(MySqlPoolHandler),
+    Sqlite(SqlitePoolHandler),
+    Clickhouse(ClickhousePoolHandler),
+    Mssql(MssqlPoolHandler),
     // Add other pool types here if needed
 }
 
 pub trait PoolHandler: Sized {
     /// Create a new pool handler
     async fn try_new(db_config: &DatabaseConfig) -> Result<Self, AppError>;
-    /// List all tables in the database
-    async fn list_tables(&self) -> Result<Vec<TableInfo>, AppError>;
+    /// Row limits configured for the database
+    fn limits(&self) -> RowLimits;
+    /// Tables of the database that may be browsed and queried
+    fn access(&self) -> &TableAccess;
+    /// SQL dialect queries are parsed with, so backend-specific syntax
+    /// (MySQL backticks, Postgres `::` casts) is accepted when sanitizing
+    fn dialect(&self) -> Box<dyn Dialect> {
+        Box::new(GenericDialect {})
+    }
+    /// How named placeholders are rewritten for this backend (see
+    /// `bind_named_params`)
+    fn placeholder_style(&self) -> PlaceholderStyle {
+        PlaceholderStyle::Question
+    }
+    /// Quote a single identifier (table, column, schema) for use in SQL,
+    /// escaping any embedded quote characters
+    fn quote_identifier(&self, ident: &str) -> String {
+        quote_with(ident, '"')
+    }
+    /// Quote a possibly schema-qualified table name as returned by
+    /// `list_tables`, e.g. `public.users` becomes `"public"."users"`.
+    /// Backends whose names are never qualified quote the whole name.
+    fn quote_table_name(&self, table_name: &str) -> String {
+        table_name
+            .splitn(2, '.')
+            .map(|part| self.quote_identifier(part))
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+    /// List the tables in the database, ordered by name, narrowed and paged
+    /// by the filter
+    async fn list_tables(&self, filter: &TableFilter) -> Result<Vec<TableInfo>, AppError>;
+    /// List the schemas holding user tables, ordered by name
+    async fn list_schemas(&self) -> Result<Vec<String>, AppError>;
     /// Get the schema of a table
     async fn get_table_schema(&self, table_name: &str) -> Result<TableSchema, AppError>;
+    /// Whether a table (or view) exists. Backends with a cheap catalog lookup
+    /// override this; the fallback fetches the schema and looks for columns.
+    async fn table_exists(&self, table_name: &str) -> Result<bool, AppError> {
+        Ok(!self.get_table_schema(table_name).await?.columns.is_empty())
+    }
+    /// Get per-column statistics of a table, keyed by column name. Backends
+    /// without statistics return an empty map.
+    async fn column_stats(
+        &self,
+        _table_name: &str,
+    ) -> Result<HashMap<String, ColumnStats>, AppError> {
+        Ok(HashMap::new())
+    }
     /// Sanitize the query and rewrite it to CTE format
-    async fn sanitize_query(&self, query: &str, limit: usize) -> Result<String, AppError> {
-        let dialect = GenericDialect {};
-        let ast = Parser::parse_sql(&dialect, query)
-            .map_err(|e| AppError::BadRequest(format!("SQL parsing error: {}", e)))?;
-        if ast.len() != 1 {
-            return Err(AppError::BadRequest(
-                "Only single SQL statements are allowed".to_string(),
-            ));
-        }
+    ///
+    /// A `LIMIT` is injected when the query has none (`LIMIT ALL` counts as
+    /// none); an explicit `LIMIT` at or above `limit` is capped at the
+    /// database's max limit, and a `LIMIT` that is not a number, such as a
+    /// bound parameter, is a `BadRequest`. When `offset` is given, an
+    /// `OFFSET` is injected the same way: a query without one gets the
+    /// requested offset, and a query with an explicit numeric `OFFSET` keeps
+    /// the larger of the two so paging never moves backwards.
+    async fn sanitize_query(
+        &self,
+        query: &str,
+        limit: usize,
+        offset: Option<usize>,
+    ) -> Result<String, AppError> {
+        sanitize_select(
+            &*self.dialect(),
+            query,
+            limit,
+            offset,
+            self.limits().max,
+            self.access(),
+        )
+    }
 
-        let mut stmt = ast.into_iter().next().unwrap();
+    /// Validate a data-modifying statement; only a single INSERT, UPDATE or
+    /// DELETE is accepted
+    async fn sanitize_mutation(&self, query: &str) -> Result<String, AppError> {
+        sanitize_dml(&*self.dialect(), query, self.access())
+    }
 
-        let has_limit = match stmt {
-            ast::Statement::Query(ref mut query) => {
-                // Check query type
-                match &*query.body {
-                    ast::SetExpr::Select(_)
-                    | ast::SetExpr::Values(_)
-                    | ast::SetExpr::Query(_)
-                    | ast::SetExpr::Table(_) => {
-                        // Valid query type
-                    }
-                    _ => {
-                        return Err(AppError::BadRequest(
-                            "Only SELECT-like queries are allowed.".to_string(),
-                        ));
+    /// Execute the query and return the result along with execution time.
+    ///
+    /// `params` are bound to the query's placeholders (see `check_params` and
+    /// `bind_params`); a count mismatch is a `BadRequest`. With `analyze`,
+    /// the plan comes from `EXPLAIN ANALYZE`, which runs the query once more
+    /// to measure it; the data is still fetched separately. Backends without
+    /// a JSON `EXPLAIN ANALYZE` reject the flag.
+    async fn execute_query(
+        &self,
+        query: &str,
+        params: &[Value],
+        limit: Option<usize>,
+        offset: Option<usize>,
+        analyze: bool,
+    ) -> Result<QueryResult, AppError>;
+
+    /// Execute an INSERT/UPDATE/DELETE and return the number of rows affected
+    async fn execute_mutation(&self, query: &str) -> Result<MutationResult, AppError>;
+
+    /// Execute the statements in order within a single transaction. If any
+    /// statement fails the transaction is rolled back and the error carries
+    /// the index of the failing statement.
+    async fn execute_batch(
+        &self,
+        statements: &[String],
+        limit: Option<usize>,
+        read_only: bool,
+    ) -> Result<Vec<StatementResult>, AppError>;
+
+    /// Run a cheap liveness check and return its round-trip latency
+    async fn health_check(&self) -> Result<Duration, AppError>;
+
+    /// Usage of the connection pool; unknown without one (e.g. ClickHouse,
+    /// which pools inside its HTTP client)
+    fn pool_stats(&self) -> PoolStats {
+        PoolStats::default()
+    }
+
+    /// Close the pool, waiting for checked-out connections to be returned
+    async fn close(&self);
+
+    /// Ask the server to cancel the statement running on the connection with
+    /// the given process id (see `BACKEND_PID`); a no-op where unsupported
+    async fn cancel_backend(&self, _pid: i32) -> Result<(), AppError> {
+        Ok(())
+    }
+
+    /// Refresh a materialized view and return how long it took. The caller
+    /// must have checked that the table is one.
+    async fn refresh_materialized_view(
+        &self,
+        _table_name: &str,
+        _concurrently: bool,
+    ) -> Result<Duration, AppError> {
+        Err(AppError::BadRequest(
+            "Materialized views are not supported by this database".to_string(),
+        ))
+    }
+
+    /// Extract the values at a JSON path from a JSON column, as rows keyed
+    /// by the column name; a value matched more than once yields more rows.
+    /// The caller must have checked that the column holds JSON.
+    async fn json_path_query(
+        &self,
+        _table_name: &str,
+        _column: &str,
+        _path: &str,
+        _limit: usize,
+    ) -> Result<QueryResult, AppError> {
+        Err(AppError::NotImplemented(
+            "JSON path extraction is only supported on Postgres".to_string(),
+        ))
+    }
+
+    /// Execute the query and stream the rows one JSON object at a time,
+    /// instead of buffering the whole result set
+    async fn execute_query_streaming(
+        &self,
+        query: &str,
+        limit: Option<usize>,
+        offset: Option<usize>,
+    ) -> Result<RowStream, AppError>;
+}
+
+/// A stream of result rows, each serialized as a JSON object
+pub type RowStream = BoxStream<'static, Result<Value, AppError>>;
+
+/// Parse a SELECT-like statement and inject or cap its `LIMIT`/`OFFSET`; see
+/// `PoolHandler::sanitize_query`
+fn sanitize_select(
+    dialect: &dyn Dialect,
+    query: &str,
+    limit: usize,
+    offset: Option<usize>,
+    max_limit: usize,
+    access: &TableAccess,
+) -> Result<String, AppError> {
+    check_query_len(query)?;
+    let ast = Parser::parse_sql(dialect, query).map_err(|e| parse_error(query, e))?;
+    if ast.len() != 1 {
+        return Err(AppError::BadRequest(
+            "Only single SQL statements are allowed".to_string(),
+        ));
+    }
+
+    let mut stmt = ast.into_iter().next().unwrap();
+
+    match stmt {
+        ast::Statement::Query(ref mut query) => {
+            // Check query type
+            match &*query.body {
+                ast::SetExpr::Select(_)
+                | ast::SetExpr::Values(_)
+                | ast::SetExpr::Query(_)
+                | ast::SetExpr::Table(_) => {
+                    // Valid query type
+                }
+                _ => {
+                    return Err(AppError::BadRequest(
+                        "Only SELECT-like queries are allowed.".to_string(),
+                    ));
+                }
+            }
+
+            match &mut query.limit {
+                Some(ast::Expr::Value(ast::ValueWithSpan {
+                    value: ast::Value::Number(s, _),
+                    ..
+                })) => {
+                    let existing_limit = s.parse::<usize>().unwrap_or(0);
+                    if existing_limit < limit {
+                        // do nothing
+                    } else {
+                        *s = min(existing_limit, max_limit).to_string();
                     }
                 }
+                // `LIMIT ALL` parses as no limit at all, so it gets `limit` too
+                None => query.limit = Some(number_expr(limit)),
+                Some(other) => {
+                    return Err(AppError::BadRequest(format!(
+                        "LIMIT must be a number, not '{}'",
+                        other
+                    )));
+                }
+            }
 
-                match &mut query.limit {
-                    Some(ast::Expr::Value(ast::ValueWithSpan {
-                        value: ast::Value::Number(s, _),
+            if let Some(offset) = offset {
+                match &mut query.offset {
+                    Some(ast::Offset {
+                        value:
+                            ast::Expr::Value(ast::ValueWithSpan {
+                                value: ast::Value::Number(s, _),
+                                ..
+                            }),
                         ..
-                    })) => {
-                        let existing_limit = s.parse::<usize>().unwrap_or(0);
-                        if existing_limit < limit {
-                            // do nothing
-                        } else {
-                            *s = min(existing_limit, MAX_LIMIT).to_string();
-                        }
-                        true
+                    }) => {
+                        let existing_offset = s.parse::<usize>().unwrap_or(0);
+                        *s = max(existing_offset, offset).to_string();
+                    }
+                    _ => {
+                        query.offset = Some(ast::Offset {
+                            value: number_expr(offset),
+                            rows: ast::OffsetRows::None,
+                        })
                     }
-                    _ => false,
                 }
             }
-            _ => {
-                return Err(AppError::BadRequest(
-                    "Only SELECT queries are allowed".to_string(),
-                ));
+        }
+        _ => {
+            return Err(AppError::BadRequest(
+                "Only SELECT queries are allowed".to_string(),
+            ));
+        }
+    };
+    // A data-modifying CTE, e.g. `WITH d AS (UPDATE ... RETURNING *) SELECT
+    // * FROM d`, writes although the statement is a query
+    let writes = ast::visit_statements(&stmt, |nested| match nested {
+        ast::Statement::Insert(_)
+        | ast::Statement::Update { .. }
+        | ast::Statement::Delete(_)
+        | ast::Statement::Merge { .. } => ControlFlow::Break(()),
+        _ => ControlFlow::Continue(()),
+    });
+    if writes.is_break() {
+        return Err(AppError::BadRequest(
+            "Queries may not contain INSERT, UPDATE, DELETE or MERGE statements".to_string(),
+        ));
+    }
+    access.check_statement(&stmt)?;
+    Ok(stmt.to_string())
+}
+
+/// Validate a single INSERT, UPDATE or DELETE statement; see
+/// `PoolHandler::sanitize_mutation`
+fn sanitize_dml(
+    dialect: &dyn Dialect,
+    query: &str,
+    access: &TableAccess,
+) -> Result<String, AppError> {
+    check_query_len(query)?;
+    let ast = Parser::parse_sql(dialect, query).map_err(|e| parse_error(query, e))?;
+    match ast.as_slice() {
+        [
+            stmt @ (ast::Statement::Insert(_)
+            | ast::Statement::Update { .. }
+            | ast::Statement::Delete(_)),
+        ] => {
+            access.check_statement(stmt)?;
+            Ok(stmt.to_string())
+        }
+        [_] => Err(AppError::BadRequest(
+            "Only INSERT, UPDATE or DELETE statements are allowed".to_string(),
+        )),
+        _ => Err(AppError::BadRequest(
+            "Only single SQL statements are allowed".to_string(),
+        )),
+    }
+}
+
+/// Validate every statement of a batch up front. Statements are sanitized
+/// like single queries, except that temporary tables may be created, and
+/// mutations are accepted when the database is not read-only.
+pub fn sanitize_batch(
+    dialect: &dyn Dialect,
+    statements: &[String],
+    limit: usize,
+    max_limit: usize,
+    read_only: bool,
+    access: &TableAccess,
+) -> Result<Vec<BatchStatement>, AppError> {
+    if statements.is_empty() {
+        return Err(AppError::BadRequest(
+            "A batch needs at least one statement".to_string(),
+        ));
+    }
+
+    let mut sanitized = Vec::with_capacity(statements.len());
+    for (index, statement) in statements.iter().enumerate() {
+        let result = if let Err(e) = check_query_len(statement) {
+            Err(e)
+        } else if is_mutation(statement) {
+            if read_only {
+                Err(AppError::BadRequest("Database is read-only".to_string()))
+            } else {
+                sanitize_dml(dialect, statement, access).map(BatchStatement::Mutation)
             }
+        } else if let Some((stmt, name)) = temp_table_statement(statement) {
+            access
+                .check_statement(&stmt)
+                .map(|()| BatchStatement::TempTable {
+                    sql: stmt.to_string(),
+                    name,
+                })
+        } else {
+            sanitize_select(dialect, statement, limit, None, max_limit, access)
+                .map(BatchStatement::Query)
         };
-        let mut sql = stmt.to_string();
-        if !has_limit {
-            sql = format!("{} LIMIT {}", sql, limit);
+        sanitized.push(result.map_err(|e| AppError::BatchStatement {
+            index,
+            source: Box::new(e),
+        })?);
+    }
+    Ok(sanitized)
+}
+
+/// Reject queries too large to be worth parsing
+fn check_query_len(query: &str) -> Result<(), AppError> {
+    if query.len() > MAX_QUERY_LEN {
+        return Err(AppError::BadRequest(format!(
+            "Query is {} bytes, the maximum is {}",
+            query.len(),
+            MAX_QUERY_LEN
+        )));
+    }
+    Ok(())
+}
+
+/// Check that the query has a placeholder for each parameter and vice versa.
+/// Placeholders are either numbered (`$1`, `?1`; the highest number counts)
+/// or bare `?` (each one counts).
+pub fn check_params(query: &str, params: &[Value]) -> Result<(), AppError> {
+    let tokens = Tokenizer::new(&GenericDialect {}, query)
+        .tokenize()
+        .map_err(|e| parse_error(query, e.into()))?;
+    let mut bare = 0;
+    let mut numbered = 0;
+    for token in tokens {
+        if let Token::Placeholder(placeholder) = token {
+            match placeholder[1..].parse::<usize>() {
+                Ok(n) => numbered = max(numbered, n),
+                Err(_) if placeholder == "?" => bare += 1,
+                Err(_) => {}
+            }
         }
-        Ok(sql)
     }
+    let expected = max(bare, numbered);
+    if expected != params.len() {
+        return Err(AppError::BadRequest(format!(
+            "Query expects {} parameter(s), but {} were given",
+            expected,
+            params.len()
+        )));
+    }
+    Ok(())
+}
 
-    /// Execute the query and return the result along with execution time
-    async fn execute_query(
-        &self,
-        query: &str,
-        limit: Option<usize>,
-    ) -> Result<QueryResult, AppError>;
+/// Bind JSON values positionally: booleans, integers, floats and strings
+/// become the matching SQL type, null a NULL text value, and arrays and
+/// objects JSON. Postgres does not coerce a text parameter to other types,
+/// so e.g. a date needs an explicit cast (`$1::date`).
+pub fn bind_params<'q, DB>(params: &'q [Value]) -> Result<DB::Arguments<'q>, AppError>
+where
+    DB: Database,
+    bool: Encode<'q, DB> + Type<DB>,
+    i64: Encode<'q, DB> + Type<DB>,
+    f64: Encode<'q, DB> + Type<DB>,
+    &'q str: Encode<'q, DB> + Type<DB>,
+    Option<&'q str>: Encode<'q, DB> + Type<DB>,
+    Json<&'q Value>: Encode<'q, DB> + Type<DB>,
+{
+    let mut args = DB::Arguments::default();
+    for (index, param) in params.iter().enumerate() {
+        let added = match param {
+            Value::Null => args.add(None::<&str>),
+            Value::Bool(b) => args.add(*b),
+            Value::Number(n) => match n.as_i64() {
+                Some(i) => args.add(i),
+                None => args.add(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            Value::String(s) => args.add(s.as_str()),
+            Value::Array(_) | Value::Object(_) => args.add(Json(param)),
+        };
+        added
+            .map_err(|e| AppError::BadRequest(format!("Invalid parameter {}: {}", index + 1, e)))?;
+    }
+    Ok(args)
+}
+
+/// Positional placeholder syntax of a backend
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaceholderStyle {
+    /// `$1`, `$2`, ...; a reused name keeps its number
+    Dollar,
+    /// `?`; a reused name is bound once per occurrence
+    Question,
+}
+
+/// Rewrite named placeholders (`:user_id`) into the backend's positional
+/// ones and return the query with the values in binding order. Without
+/// named placeholders or values, the query and `params` are returned as
+/// they are. Named and positional placeholders can't be mixed, and every
+/// named placeholder needs a value and every value a placeholder.
+pub fn bind_named_params(
+    query: &str,
+    params: &[Value],
+    named: &Map<String, Value>,
+    style: PlaceholderStyle,
+) -> Result<(String, Vec<Value>), AppError> {
+    let tokens = Tokenizer::new(&GenericDialect {}, query)
+        .tokenize_with_location()
+        .map_err(|e| parse_error(query, e.into()))?;
+
+    // Byte ranges of the named placeholders, with their names
+    let mut placeholders = Vec::new();
+    let mut positional = false;
+    for (index, token) in tokens.iter().enumerate() {
+        match &token.token {
+            Token::Placeholder(_) => positional = true,
+            Token::Colon => {
+                let Some(next) = tokens.get(index + 1) else {
+                    continue;
+                };
+                let Token::Word(word) = &next.token else {
+                    continue;
+                };
+                // A colon right after a value is not a placeholder, e.g. in
+                // an array slice (`a[lo:hi]`)
+                let after_value =
+                    index
+                        .checked_sub(1)
+                        .is_some_and(|previous| match &tokens[previous].token {
+                            Token::Word(w) => {
+                                w.keyword == Keyword::NoKeyword || w.quote_style.is_some()
+                            }
+                            Token::Number(..)
+                            | Token::RParen
+                            | Token::RBracket
+                            | Token::SingleQuotedString(_) => true,
+                            _ => false,
+                        });
+                if next.span.start == token.span.end && !after_value {
+                    placeholders.push((
+                        byte_offset(query, token.span.start),
+                        byte_offset(query, next.span.end),
+                        word.value.as_str(),
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if placeholders.is_empty() && named.is_empty() {
+        return Ok((query.to_string(), params.to_vec()));
+    }
+    if positional || !params.is_empty() {
+        return Err(AppError::BadRequest(
+            "Named and positional parameters can't be mixed".to_string(),
+        ));
+    }
+    if let Some(unused) = named
+        .keys()
+        .find(|name| !placeholders.iter().any(|(_, _, used)| used == name))
+    {
+        return Err(AppError::BadRequest(format!(
+            "Query has no placeholder :{}",
+            unused
+        )));
+    }
+
+    let mut rewritten = String::with_capacity(query.len());
+    let mut values: Vec<Value> = Vec::new();
+    let mut numbers: Vec<&str> = Vec::new(); // Names in order of first use
+    let mut copied = 0;
+    for (start, end, name) in placeholders {
+        let value = named.get(name).ok_or_else(|| {
+            AppError::BadRequest(format!("No value given for placeholder :{}", name))
+        })?;
+        rewritten.push_str(&query[copied..start]);
+        match style {
+            PlaceholderStyle::Dollar => {
+                let number = match numbers.iter().position(|n| *n == name) {
+                    Some(index) => index + 1,
+                    None => {
+                        numbers.push(name);
+                        values.push(value.clone());
+                        numbers.len()
+                    }
+                };
+                rewritten.push_str(&format!("${}", number));
+            }
+            PlaceholderStyle::Question => {
+                values.push(value.clone());
+                rewritten.push('?');
+            }
+        }
+        copied = end;
+    }
+    rewritten.push_str(&query[copied..]);
+    Ok((rewritten, values))
+}
+
+/// Substitute the `{{name}}` template variables of a query. This is string
+/// templating, not SQL parameters, and is meant for identifiers: a value made
+/// of identifier parts (`orders_2024`, `archive.orders`) is pasted into the
+/// query as is. Any other value is never pasted; its variable becomes the
+/// named placeholder `:name` and the value is returned to be bound with the
+/// other named parameters (as a number when it is one, else as text). Every
+/// variable needs a value and every value a variable. Without values the
+/// query is returned as it is.
+pub fn apply_template_vars(
+    query: &str,
+    vars: &HashMap<String, String>,
+    named: &Map<String, Value>,
+) -> Result<(String, Map<String, Value>), AppError> {
+    let mut literals = Map::new();
+    if vars.is_empty() {
+        return Ok((query.to_string(), literals));
+    }
+
+    let mut rewritten = String::with_capacity(query.len());
+    let mut used = Vec::new();
+    let mut rest = query;
+    while let Some(start) = rest.find("{{") {
+        let end = rest[start..]
+            .find("}}")
+            .map(|end| start + end)
+            .ok_or_else(|| AppError::BadRequest("Unclosed template variable".to_string()))?;
+        let name = rest[start + 2..end].trim();
+        if !is_identifier(name) {
+            return Err(AppError::BadRequest(format!(
+                "Invalid template variable name '{}'",
+                name
+            )));
+        }
+        let value = vars.get(name).ok_or_else(|| {
+            AppError::BadRequest(format!("No value given for template variable {}", name))
+        })?;
+        rewritten.push_str(&rest[..start]);
+        if value.split('.').all(is_identifier) {
+            rewritten.push_str(value);
+        } else {
+            if named.contains_key(name) {
+                return Err(AppError::BadRequest(format!(
+                    "Template variable {} clashes with the named parameter :{}",
+                    name, name
+                )));
+            }
+            let literal = value
+                .parse()
+                .map_or_else(|_| Value::String(value.clone()), Value::Number);
+            literals.insert(name.to_string(), literal);
+            rewritten.push(':');
+            rewritten.push_str(name);
+        }
+        used.push(name);
+        rest = &rest[end + 2..];
+    }
+    rewritten.push_str(rest);
+
+    if let Some(unused) = vars.keys().find(|name| !used.contains(&name.as_str())) {
+        return Err(AppError::BadRequest(format!(
+            "Query has no template variable {{{{{}}}}}",
+            unused
+        )));
+    }
+    Ok((rewritten, literals))
+}
+
+/// Whether a name is a plain, unquoted identifier: a letter or underscore
+/// followed by letters, digits and underscores
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Byte offset in `text` of a tokenizer location (1-based line and column,
+/// counted in chars)
+fn byte_offset(text: &str, location: Location) -> usize {
+    let line_start: usize = text
+        .split_inclusive('\n')
+        .take(location.line.saturating_sub(1) as usize)
+        .map(str::len)
+        .sum();
+    let column = location.column.saturating_sub(1) as usize;
+    line_start
+        + text[line_start..]
+            .char_indices()
+            .nth(column)
+            .map_or(text.len() - line_start, |(offset, _)| offset)
+}
+
+/// Turn a sqlparser error into `AppError::SqlParsingError`, recovering the
+/// position sqlparser appends to its messages (" at Line: 1, Column: 8").
+/// The message leaves out sqlparser's own "sql parser error: " prefix, as
+/// `AppError` adds one.
+fn parse_error(query: &str, e: ParserError) -> AppError {
+    let message = match e {
+        ParserError::TokenizerError(message) | ParserError::ParserError(message) => message,
+        ParserError::RecursionLimitExceeded => "recursion limit exceeded".to_string(),
+    };
+    let position = message.rfind(" at Line: ").and_then(|start| {
+        let (line, column) = message[start + " at Line: ".len()..].split_once(", Column: ")?;
+        let column: String = column.chars().take_while(char::is_ascii_digit).collect();
+        Some((
+            start,
+            line.parse::<usize>().ok()?,
+            column.parse::<usize>().ok()?,
+        ))
+    });
+    let Some((start, line, column)) = position else {
+        return AppError::SqlParsingError {
+            message,
+            line: None,
+            column: None,
+            near: None,
+        };
+    };
+
+    let near = query
+        .lines()
+        .nth(line.saturating_sub(1))
+        .map(|text| {
+            text.chars()
+                .skip(column.saturating_sub(1))
+                .take(20)
+                .collect::<String>()
+        })
+        .filter(|text| !text.is_empty());
+    AppError::SqlParsingError {
+        message: message[..start].to_string(),
+        line: Some(line),
+        column: Some(column),
+        near,
+    }
+}
+
+/// Wrap an identifier in the given quote character, doubling any occurrence
+/// of it inside the identifier
+fn quote_with(ident: &str, quote: char) -> String {
+    let escaped = ident.replace(quote, &format!("{quote}{quote}"));
+    format!("{quote}{escaped}{quote}")
+}
+
+/// Whether the query is a data-modifying statement (INSERT, UPDATE or DELETE)
+/// that must go through `execute_mutation` rather than `execute_query`
+pub fn is_mutation(query: &str) -> bool {
+    Parser::parse_sql(&GenericDialect {}, query).is_ok_and(|ast| {
+        matches!(
+            ast.as_slice(),
+            [
+                ast::Statement::Insert(_)
+                    | ast::Statement::Update { .. }
+                    | ast::Statement::Delete(_)
+            ]
+        )
+    })
+}
+
+/// If the query is a single `CREATE TEMPORARY TABLE`, return it parsed,
+/// with the name of the table. Backends drop the table before the batch's
+/// connection goes back to the pool, so it is allowed even on read-only
+/// databases.
+fn temp_table_statement(query: &str) -> Option<(ast::Statement, String)> {
+    let mut ast = Parser::parse_sql(&GenericDialect {}, query).ok()?;
+    match ast.as_slice() {
+        [ast::Statement::CreateTable(create)] if create.temporary => {
+            let name = create.name.to_string();
+            ast.pop().map(|stmt| (stmt, name))
+        }
+        _ => None,
+    }
+}
+
+/// Build a numeric literal expression for LIMIT/OFFSET rewriting
+fn number_expr(n: usize) -> ast::Expr {
+    ast::Expr::value(ast::Value::Number(n.to_string(), false))
 }
 
 // Response structure for the /api/databases endpoint
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct DatabaseInfo {
     pub name: String,
+    /// Deprecated string form of `kind`, kept for one release
     #[serde(rename = "type")]
-    pub db_type: String, // Use String representation for JSON response
+    pub db_type: String,
+    pub kind: DatabaseType,
+    pub connected: bool, // Whether a connection pool is currently available
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 #[serde(rename_all = "snake_case")]
 pub enum TableType {
     Table,
     View,
     MaterializedView,
+    ForeignTable,
+    PartitionedTable,
 }
 // Response structure for the /api/databases/{dbName}/tables endpoint
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)] // Derive FromRow for sqlx query mapping
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct TableInfo {
     pub name: String,
     #[sqlx(rename = "type", try_from = "String")]
     #[serde(rename = "type")]
     pub table_type: TableType, // e.g., "BASE TABLE", "VIEW"
+    #[sqlx(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub row_estimate: Option<i64>, // Approximate row count, only filled on request
+    #[sqlx(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>, // Comment on the table, where the backend has them
+}
+
+// Query parameters for the /api/databases/{dbName}/tables endpoint
+#[derive(Debug, Clone, Default, Deserialize)]
+#[cfg_attr(
+    feature = "openapi",
+    derive(utoipa::IntoParams),
+    into_params(parameter_in = Query)
+)]
+pub struct TableFilter {
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+    /// Case-insensitive LIKE pattern matched against the table name, e.g. `%user%`
+    pub name_filter: Option<String>,
+    /// Also fetch an approximate row count per table (best-effort)
+    #[serde(default)]
+    pub include_estimates: bool,
+    /// Only list the tables of this schema (a database on MySQL and ClickHouse)
+    pub schema: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub enum ColumnType {
     // Numeric types
     SmallInt,
@@ -190,12 +968,17 @@ pub enum ColumnType {
     Xml,
     // Money
     Money,
+    // MySQL-only types
+    Enum,
+    Set,
+    Year,
     // Other
     Other(String),
 }
 
 // Structures for /api/.../schema endpoint
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct ColumnInfo {
     pub name: String,
     pub data_type: ColumnType,
@@ -209,11 +992,28 @@ pub struct ColumnInfo {
     pub fk_table: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub fk_column: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<ColumnStats>, // Only filled when explicitly requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enum_values: Option<Vec<String>>, // Labels of a Postgres enum column, in order
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>, // Comment on the column
+}
+
+/// Planner statistics for a column, as estimated by the database
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ColumnStats {
+    pub distinct_estimate: Option<f64>,
+    pub null_fraction: Option<f64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct TableSchema {
     pub table_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>, // Comment on the table
     pub columns: Vec<ColumnInfo>,
     // Optional: Add constraints, indexes later if needed
     // pub constraints: Option<Vec<ConstraintInfo>>,
@@ -221,12 +1021,80 @@ pub struct TableSchema {
 }
 
 // Struct to hold the query result and execution time
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct QueryResult {
     pub data: Value,
     pub execution_time: Duration,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub plan: Option<Value>,
+    /// Warnings and notices the database raised while running the query
+    pub warnings: Vec<String>,
+}
+
+impl QueryResult {
+    /// Rows in `data`, which is an array of rows or null
+    pub fn row_count(&self) -> u64 {
+        self.data.as_array().map_or(0, Vec::len) as u64
+    }
+}
+
+/// Connections of a pool, for the /api/databases/{db_name}/pool-stats
+/// endpoint. Fields are null where the backend can't tell.
+#[derive(Debug, Clone, Copy, Default, Serialize, PartialEq, Eq)]
+pub struct PoolStats {
+    pub size: Option<u32>, // Open connections, idle or in use
+    pub idle: Option<u32>,
+    pub in_use: Option<u32>,
+    pub max_size: Option<u32>, // Most connections the pool will open
+}
+
+impl PoolStats {
+    fn of<DB: Database>(pool: &sqlx::Pool<DB>) -> Self {
+        let size = pool.size();
+        let idle = u32::try_from(pool.num_idle()).unwrap_or(u32::MAX);
+        PoolStats {
+            size: Some(size),
+            idle: Some(idle),
+            in_use: Some(size.saturating_sub(idle)),
+            max_size: Some(pool.options().get_max_connections()),
+        }
+    }
+}
+
+// Outcome of an INSERT/UPDATE/DELETE
+#[derive(Debug, Serialize)]
+pub struct MutationResult {
+    pub affected_rows: u64,
+    pub execution_time: Duration,
+}
+
+/// A sanitized statement of a batch
+#[derive(Debug)]
+pub enum BatchStatement {
+    Query(String),
+    Mutation(String),
+    /// A `CREATE TEMPORARY TABLE`, and the name of the table
+    TempTable {
+        sql: String,
+        name: String,
+    },
+}
+
+impl BatchStatement {
+    /// Name of the temporary table the statement creates, if any
+    pub fn temp_table(&self) -> Option<&str> {
+        match self {
+            BatchStatement::TempTable { name, .. } => Some(name),
+            _ => None,
+        }
+    }
+}
+
+/// Outcome of one statement of a batch
+#[derive(Debug)]
+pub enum StatementResult {
+    Query(QueryResult),
+    Mutation(MutationResult),
 }
 
 #[derive(sqlx::FromRow)]
@@ -242,14 +1110,20 @@ impl FromStr for TableType {
             "table" => Ok(TableType::Table),
             "view" => Ok(TableType::View),
             "materialized_view" => Ok(TableType::MaterializedView),
-            _ => unreachable!(),
+            "foreign_table" => Ok(TableType::ForeignTable),
+            "partitioned_table" => Ok(TableType::PartitionedTable),
+            other => {
+                tracing::warn!("Unknown table type '{}', treating it as a table", other);
+                Ok(TableType::Table)
+            }
         }
     }
 }
 
 impl From<String> for TableType {
     fn from(s: String) -> Self {
-        TableType::from_str(&s).unwrap()
+        let Ok(table_type) = TableType::from_str(&s);
+        table_type
     }
 }
 
@@ -259,6 +1133,8 @@ impl TableType {
             TableType::Table => "table",
             TableType::View => "view",
             TableType::MaterializedView => "materialized_view",
+            TableType::ForeignTable => "foreign_table",
+            TableType::PartitionedTable => "partitioned_table",
         }
     }
 }
@@ -266,18 +1142,23 @@ impl TableType {
 impl FromStr for ColumnType {
     type Err = Infallible;
 
-    // TODO: verify mysql types
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // MySQL attributes don't change how a value is displayed or queried
+        let s = s
+            .trim_end_matches(" zerofill")
+            .trim_end_matches(" unsigned");
         match s {
-            "smallint" => Ok(ColumnType::SmallInt),
-            "integer" => Ok(ColumnType::Integer),
+            "smallint" | "tinyint" => Ok(ColumnType::SmallInt),
+            "integer" | "int" | "mediumint" => Ok(ColumnType::Integer),
             "bigint" => Ok(ColumnType::BigInt),
             "decimal" => Ok(ColumnType::Decimal),
             "numeric" => Ok(ColumnType::Numeric),
             "real" => Ok(ColumnType::Real),
-            "double precision" => Ok(ColumnType::DoublePrecision),
+            "double precision" | "double" => Ok(ColumnType::DoublePrecision),
+            "float" => Ok(ColumnType::Real),
+            "bool" => Ok(ColumnType::Boolean),
             "money" => Ok(ColumnType::Money),
-            "text" => Ok(ColumnType::Text),
+            "text" | "tinytext" | "mediumtext" | "longtext" => Ok(ColumnType::Text),
             "char" => Ok(ColumnType::Char),
             "character" => Ok(ColumnType::Char),
             "varchar" => Ok(ColumnType::Varchar),
@@ -285,7 +1166,9 @@ impl FromStr for ColumnType {
             "boolean" => Ok(ColumnType::Boolean),
             "json" => Ok(ColumnType::Json),
             "jsonb" => Ok(ColumnType::Jsonb),
-            "bytea" => Ok(ColumnType::Bytea),
+            "bytea" | "binary" | "varbinary" | "tinyblob" | "blob" | "mediumblob" | "longblob" => {
+                Ok(ColumnType::Bytea)
+            }
             "uuid" => Ok(ColumnType::Uuid),
             "inet" => Ok(ColumnType::Inet),
             "cidr" => Ok(ColumnType::Cidr),
@@ -315,19 +1198,24 @@ impl FromStr for ColumnType {
             "tsvector" => Ok(ColumnType::TsVector),
             "tsquery" => Ok(ColumnType::TsQuery),
             "xml" => Ok(ColumnType::Xml),
-            v => Ok(ColumnType::Other(v.to_string())),
+            "enum" => Ok(ColumnType::Enum),
+            "set" => Ok(ColumnType::Set),
+            "year" => Ok(ColumnType::Year),
+            v => {
+                // Extension and user-defined types are expected, and seen on
+                // every schema fetch, so this is no warning
+                tracing::debug!("Unsupported column type '{}', keeping it as-is", v);
+                Ok(ColumnType::Other(v.to_string()))
+            }
         }
     }
 }
 
 impl From<String> for ColumnType {
     fn from(s: String) -> Self {
-        ColumnType::from_str(&s).unwrap_or_else(|_| {
-            tracing::warn!("Unsupported database type string encountered: {}", s);
-            // Decide on a default/fallback type if needed, or keep panicking/unreachable
-            // For now, stick with unreachable! based on previous code
-            unreachable!("unsupported type: {}", s)
-        })
+        // Parsing never fails: unknown types become `ColumnType::Other`
+        let Ok(column_type) = ColumnType::from_str(&s);
+        column_type
     }
 }
 
@@ -346,6 +1234,30 @@ impl<'r> sqlx::Decode<'r, sqlx::Postgres> for ColumnType {
     }
 }
 
+impl DbPool {
+    /// Backend name, as used in configuration (`db_type`)
+    pub fn backend(&self) -> &'static str {
+        match self {
+            DbPool::Postgres(_) => "postgres",
+            DbPool::MySql(_) => "mysql",
+            DbPool::Sqlite(_) => "sqlite",
+            DbPool::Clickhouse(_) => "clickhouse",
+            DbPool::Mssql(_) => "mssql",
+        }
+    }
+
+    /// Build a `SELECT * ... LIMIT n` preview of a table, as named by
+    /// `list_tables`, with its identifiers quoted for the backend.
+    pub fn sample_query(&self, table_name: &str, limit: usize) -> String {
+        let table_name = self.quote_table_name(table_name);
+        match self {
+            // SQL Server has no LIMIT
+            DbPool::Mssql(_) => format!("SELECT TOP {} * FROM {}", limit, table_name),
+            _ => format!("SELECT * FROM {} LIMIT {}", table_name, limit),
+        }
+    }
+}
+
 impl PoolHandler for DbPool {
     async fn try_new(db_config: &DatabaseConfig) -> Result<Self, AppError> {
         match db_config.db_type {
@@ -357,6 +1269,18 @@ impl PoolHandler for DbPool {
                 let pool = MySqlPoolHandler::try_new(db_config).await?;
                 Ok(DbPool::MySql(pool))
             }
+            DatabaseType::Sqlite => {
+                let pool = SqlitePoolHandler::try_new(db_config).await?;
+                Ok(DbPool::Sqlite(pool))
+            }
+            DatabaseType::Clickhouse => {
+                let pool = ClickhousePoolHandler::try_new(db_config).await?;
+                Ok(DbPool::Clickhouse(pool))
+            }
+            DatabaseType::Mssql => {
+                let pool = MssqlPoolHandler::try_new(db_config).await?;
+                Ok(DbPool::Mssql(pool))
+            }
             #[allow(unreachable_patterns)]
             _ => Err(AppError::UnsupportedDatabaseType(
                 db_config.db_type.to_string(),
@@ -364,36 +1288,650 @@ impl PoolHandler for DbPool {
         }
     }
 
-    async fn list_tables(&self) -> Result<Vec<TableInfo>, AppError> {
+    fn limits(&self) -> RowLimits {
+        match self {
+            DbPool::Postgres(pg_pool) => pg_pool.limits(),
+            DbPool::MySql(mysql_pool) => mysql_pool.limits(),
+            DbPool::Sqlite(sqlite_pool) => sqlite_pool.limits(),
+            DbPool::Clickhouse(clickhouse_pool) => clickhouse_pool.limits(),
+            DbPool::Mssql(mssql_pool) => mssql_pool.limits(),
+        }
+    }
+
+    fn access(&self) -> &TableAccess {
         match self {
-            DbPool::Postgres(pg_pool) => pg_pool.list_tables().await,
-            DbPool::MySql(mysql_pool) => mysql_pool.list_tables().await,
+            DbPool::Postgres(pg_pool) => pg_pool.access(),
+            DbPool::MySql(mysql_pool) => mysql_pool.access(),
+            DbPool::Sqlite(sqlite_pool) => sqlite_pool.access(),
+            DbPool::Clickhouse(clickhouse_pool) => clickhouse_pool.access(),
+            DbPool::Mssql(mssql_pool) => mssql_pool.access(),
+        }
+    }
+
+    fn pool_stats(&self) -> PoolStats {
+        match self {
+            DbPool::Postgres(pg_pool) => pg_pool.pool_stats(),
+            DbPool::MySql(mysql_pool) => mysql_pool.pool_stats(),
+            DbPool::Sqlite(sqlite_pool) => sqlite_pool.pool_stats(),
+            DbPool::Clickhouse(clickhouse_pool) => clickhouse_pool.pool_stats(),
+            DbPool::Mssql(mssql_pool) => mssql_pool.pool_stats(),
+        }
+    }
+
+    fn dialect(&self) -> Box<dyn Dialect> {
+        match self {
+            DbPool::Postgres(pg_pool) => pg_pool.dialect(),
+            DbPool::MySql(mysql_pool) => mysql_pool.dialect(),
+            DbPool::Sqlite(sqlite_pool) => sqlite_pool.dialect(),
+            DbPool::Clickhouse(clickhouse_pool) => clickhouse_pool.dialect(),
+            DbPool::Mssql(mssql_pool) => mssql_pool.dialect(),
+        }
+    }
+
+    fn placeholder_style(&self) -> PlaceholderStyle {
+        match self {
+            DbPool::Postgres(pg_pool) => pg_pool.placeholder_style(),
+            DbPool::MySql(mysql_pool) => mysql_pool.placeholder_style(),
+            DbPool::Sqlite(sqlite_pool) => sqlite_pool.placeholder_style(),
+            DbPool::Clickhouse(clickhouse_pool) => clickhouse_pool.placeholder_style(),
+            DbPool::Mssql(mssql_pool) => mssql_pool.placeholder_style(),
+        }
+    }
+
+    fn quote_identifier(&self, ident: &str) -> String {
+        match self {
+            DbPool::Postgres(pg_pool) => pg_pool.quote_identifier(ident),
+            DbPool::MySql(mysql_pool) => mysql_pool.quote_identifier(ident),
+            DbPool::Sqlite(sqlite_pool) => sqlite_pool.quote_identifier(ident),
+            DbPool::Clickhouse(clickhouse_pool) => clickhouse_pool.quote_identifier(ident),
+            DbPool::Mssql(mssql_pool) => mssql_pool.quote_identifier(ident),
+        }
+    }
+
+    fn quote_table_name(&self, table_name: &str) -> String {
+        match self {
+            DbPool::Postgres(pg_pool) => pg_pool.quote_table_name(table_name),
+            DbPool::MySql(mysql_pool) => mysql_pool.quote_table_name(table_name),
+            DbPool::Sqlite(sqlite_pool) => sqlite_pool.quote_table_name(table_name),
+            DbPool::Clickhouse(clickhouse_pool) => clickhouse_pool.quote_table_name(table_name),
+            DbPool::Mssql(mssql_pool) => mssql_pool.quote_table_name(table_name),
+        }
+    }
+
+    async fn list_tables(&self, filter: &TableFilter) -> Result<Vec<TableInfo>, AppError> {
+        // With access rules, page over the accessible tables only
+        let access = self.access();
+        let unpaged = TableFilter {
+            limit: None,
+            offset: None,
+            ..filter.clone()
+        };
+        let backend_filter = if access.is_unrestricted() {
+            filter
+        } else {
+            &unpaged
+        };
+        let tables = match self {
+            DbPool::Postgres(pg_pool) => pg_pool.list_tables(backend_filter).await,
+            DbPool::MySql(mysql_pool) => mysql_pool.list_tables(backend_filter).await,
+            DbPool::Sqlite(sqlite_pool) => sqlite_pool.list_tables(backend_filter).await,
+            DbPool::Clickhouse(clickhouse_pool) => {
+                clickhouse_pool.list_tables(backend_filter).await
+            }
+            DbPool::Mssql(mssql_pool) => mssql_pool.list_tables(backend_filter).await,
+        }?;
+        if access.is_unrestricted() {
+            Ok(tables)
+        } else {
+            Ok(access.filter_tables(tables, filter))
+        }
+    }
+
+    async fn list_schemas(&self) -> Result<Vec<String>, AppError> {
+        // With access rules, only schemas holding an accessible table are listed
+        let access = self.access();
+        if !access.is_unrestricted() {
+            let tables = self.list_tables(&TableFilter::default()).await?;
+            let mut schemas: Vec<String> = tables
+                .into_iter()
+                .map(|table| match table.name.split_once('.') {
+                    Some((schema, _)) => schema.to_string(),
+                    None => "main".to_string(), // SQLite names are unqualified
+                })
+                .collect();
+            schemas.dedup();
+            return Ok(schemas);
+        }
+        match self {
+            DbPool::Postgres(pg_pool) => pg_pool.list_schemas().await,
+            DbPool::MySql(mysql_pool) => mysql_pool.list_schemas().await,
+            DbPool::Sqlite(sqlite_pool) => sqlite_pool.list_schemas().await,
+            DbPool::Clickhouse(clickhouse_pool) => clickhouse_pool.list_schemas().await,
+            DbPool::Mssql(mssql_pool) => mssql_pool.list_schemas().await,
         }
     }
 
     // Add method signature for getting table schema
     async fn get_table_schema(&self, table_name: &str) -> Result<TableSchema, AppError> {
+        self.access().check_table(table_name)?;
         match self {
             DbPool::Postgres(pg_pool) => pg_pool.get_table_schema(table_name).await,
             DbPool::MySql(mysql_pool) => mysql_pool.get_table_schema(table_name).await,
+            DbPool::Sqlite(sqlite_pool) => sqlite_pool.get_table_schema(table_name).await,
+            DbPool::Clickhouse(clickhouse_pool) => {
+                clickhouse_pool.get_table_schema(table_name).await
+            }
+            DbPool::Mssql(mssql_pool) => mssql_pool.get_table_schema(table_name).await,
+        }
+    }
+
+    async fn table_exists(&self, table_name: &str) -> Result<bool, AppError> {
+        self.access().check_table(table_name)?;
+        match self {
+            DbPool::Postgres(pg_pool) => pg_pool.table_exists(table_name).await,
+            DbPool::MySql(mysql_pool) => mysql_pool.table_exists(table_name).await,
+            DbPool::Sqlite(sqlite_pool) => sqlite_pool.table_exists(table_name).await,
+            DbPool::Clickhouse(clickhouse_pool) => clickhouse_pool.table_exists(table_name).await,
+            DbPool::Mssql(mssql_pool) => mssql_pool.table_exists(table_name).await,
         }
     }
 
-    async fn sanitize_query(&self, query: &str, limit: usize) -> Result<String, AppError> {
+    async fn column_stats(
+        &self,
+        table_name: &str,
+    ) -> Result<HashMap<String, ColumnStats>, AppError> {
+        self.access().check_table(table_name)?;
         match self {
-            DbPool::Postgres(pg_pool) => pg_pool.sanitize_query(query, limit).await,
-            DbPool::MySql(mysql_pool) => mysql_pool.sanitize_query(query, limit).await,
+            DbPool::Postgres(pg_pool) => pg_pool.column_stats(table_name).await,
+            DbPool::MySql(mysql_pool) => mysql_pool.column_stats(table_name).await,
+            DbPool::Sqlite(sqlite_pool) => sqlite_pool.column_stats(table_name).await,
+            DbPool::Clickhouse(clickhouse_pool) => clickhouse_pool.column_stats(table_name).await,
+            DbPool::Mssql(mssql_pool) => mssql_pool.column_stats(table_name).await,
+        }
+    }
+
+    async fn sanitize_query(
+        &self,
+        query: &str,
+        limit: usize,
+        offset: Option<usize>,
+    ) -> Result<String, AppError> {
+        match self {
+            DbPool::Postgres(pg_pool) => pg_pool.sanitize_query(query, limit, offset).await,
+            DbPool::MySql(mysql_pool) => mysql_pool.sanitize_query(query, limit, offset).await,
+            DbPool::Sqlite(sqlite_pool) => sqlite_pool.sanitize_query(query, limit, offset).await,
+            DbPool::Clickhouse(clickhouse_pool) => {
+                clickhouse_pool.sanitize_query(query, limit, offset).await
+            }
+            DbPool::Mssql(mssql_pool) => mssql_pool.sanitize_query(query, limit, offset).await,
         }
     }
 
+    #[instrument(
+        skip(self, query, params),
+        fields(backend = self.backend(), query = %truncate_for_log(query), rows = field::Empty)
+    )]
     async fn execute_query(
         &self,
         query: &str,
+        params: &[Value],
         limit: Option<usize>,
+        offset: Option<usize>,
+        analyze: bool,
     ) -> Result<QueryResult, AppError> {
+        let result = match self {
+            DbPool::Postgres(pg_pool) => {
+                pg_pool
+                    .execute_query(query, params, limit, offset, analyze)
+                    .await
+            }
+            DbPool::MySql(mysql_pool) => {
+                mysql_pool
+                    .execute_query(query, params, limit, offset, analyze)
+                    .await
+            }
+            DbPool::Sqlite(sqlite_pool) => {
+                sqlite_pool
+                    .execute_query(query, params, limit, offset, analyze)
+                    .await
+            }
+            DbPool::Clickhouse(clickhouse_pool) => {
+                clickhouse_pool
+                    .execute_query(query, params, limit, offset, analyze)
+                    .await
+            }
+            DbPool::Mssql(mssql_pool) => {
+                mssql_pool
+                    .execute_query(query, params, limit, offset, analyze)
+                    .await
+            }
+        };
+        if let Ok(query_result) = &result {
+            let rows = query_result.data.as_array().map_or(0, Vec::len);
+            Span::current().record("rows", rows);
+        }
+        result
+    }
+
+    async fn execute_mutation(&self, query: &str) -> Result<MutationResult, AppError> {
         match self {
-            DbPool::Postgres(pg_pool) => pg_pool.execute_query(query, limit).await,
-            DbPool::MySql(mysql_pool) => mysql_pool.execute_query(query, limit).await,
+            DbPool::Postgres(pg_pool) => pg_pool.execute_mutation(query).await,
+            DbPool::MySql(mysql_pool) => mysql_pool.execute_mutation(query).await,
+            DbPool::Sqlite(sqlite_pool) => sqlite_pool.execute_mutation(query).await,
+            DbPool::Clickhouse(clickhouse_pool) => clickhouse_pool.execute_mutation(query).await,
+            DbPool::Mssql(mssql_pool) => mssql_pool.execute_mutation(query).await,
         }
     }
+
+    async fn execute_batch(
+        &self,
+        statements: &[String],
+        limit: Option<usize>,
+        read_only: bool,
+    ) -> Result<Vec<StatementResult>, AppError> {
+        match self {
+            DbPool::Postgres(pg_pool) => pg_pool.execute_batch(statements, limit, read_only).await,
+            DbPool::MySql(mysql_pool) => {
+                mysql_pool.execute_batch(statements, limit, read_only).await
+            }
+            DbPool::Sqlite(sqlite_pool) => {
+                sqlite_pool
+                    .execute_batch(statements, limit, read_only)
+                    .await
+            }
+            DbPool::Clickhouse(clickhouse_pool) => {
+                clickhouse_pool
+                    .execute_batch(statements, limit, read_only)
+                    .await
+            }
+            DbPool::Mssql(mssql_pool) => {
+                mssql_pool.execute_batch(statements, limit, read_only).await
+            }
+        }
+    }
+
+    async fn health_check(&self) -> Result<Duration, AppError> {
+        match self {
+            DbPool::Postgres(pg_pool) => pg_pool.health_check().await,
+            DbPool::MySql(mysql_pool) => mysql_pool.health_check().await,
+            DbPool::Sqlite(sqlite_pool) => sqlite_pool.health_check().await,
+            DbPool::Clickhouse(clickhouse_pool) => clickhouse_pool.health_check().await,
+            DbPool::Mssql(mssql_pool) => mssql_pool.health_check().await,
+        }
+    }
+
+    async fn close(&self) {
+        match self {
+            DbPool::Postgres(pg_pool) => pg_pool.close().await,
+            DbPool::MySql(mysql_pool) => mysql_pool.close().await,
+            DbPool::Sqlite(sqlite_pool) => sqlite_pool.close().await,
+            DbPool::Clickhouse(clickhouse_pool) => clickhouse_pool.close().await,
+            DbPool::Mssql(mssql_pool) => mssql_pool.close().await,
+        }
+    }
+
+    async fn cancel_backend(&self, pid: i32) -> Result<(), AppError> {
+        match self {
+            DbPool::Postgres(pg_pool) => pg_pool.cancel_backend(pid).await,
+            DbPool::MySql(mysql_pool) => mysql_pool.cancel_backend(pid).await,
+            DbPool::Sqlite(sqlite_pool) => sqlite_pool.cancel_backend(pid).await,
+            DbPool::Clickhouse(clickhouse_pool) => clickhouse_pool.cancel_backend(pid).await,
+            DbPool::Mssql(mssql_pool) => mssql_pool.cancel_backend(pid).await,
+        }
+    }
+
+    async fn refresh_materialized_view(
+        &self,
+        table_name: &str,
+        concurrently: bool,
+    ) -> Result<Duration, AppError> {
+        match self {
+            DbPool::Postgres(pg_pool) => {
+                pg_pool
+                    .refresh_materialized_view(table_name, concurrently)
+                    .await
+            }
+            DbPool::MySql(mysql_pool) => {
+                mysql_pool
+                    .refresh_materialized_view(table_name, concurrently)
+                    .await
+            }
+            DbPool::Sqlite(sqlite_pool) => {
+                sqlite_pool
+                    .refresh_materialized_view(table_name, concurrently)
+                    .await
+            }
+            DbPool::Clickhouse(clickhouse_pool) => {
+                clickhouse_pool
+                    .refresh_materialized_view(table_name, concurrently)
+                    .await
+            }
+            DbPool::Mssql(mssql_pool) => {
+                mssql_pool
+                    .refresh_materialized_view(table_name, concurrently)
+                    .await
+            }
+        }
+    }
+
+    async fn json_path_query(
+        &self,
+        table_name: &str,
+        column: &str,
+        path: &str,
+        limit: usize,
+    ) -> Result<QueryResult, AppError> {
+        match self {
+            DbPool::Postgres(pg_pool) => {
+                pg_pool
+                    .json_path_query(table_name, column, path, limit)
+                    .await
+            }
+            DbPool::MySql(mysql_pool) => {
+                mysql_pool
+                    .json_path_query(table_name, column, path, limit)
+                    .await
+            }
+            DbPool::Sqlite(sqlite_pool) => {
+                sqlite_pool
+                    .json_path_query(table_name, column, path, limit)
+                    .await
+            }
+            DbPool::Clickhouse(clickhouse_pool) => {
+                clickhouse_pool
+                    .json_path_query(table_name, column, path, limit)
+                    .await
+            }
+            DbPool::Mssql(mssql_pool) => {
+                mssql_pool
+                    .json_path_query(table_name, column, path, limit)
+                    .await
+            }
+        }
+    }
+
+    async fn execute_query_streaming(
+        &self,
+        query: &str,
+        limit: Option<usize>,
+        offset: Option<usize>,
+    ) -> Result<RowStream, AppError> {
+        match self {
+            DbPool::Postgres(pg_pool) => {
+                pg_pool.execute_query_streaming(query, limit, offset).await
+            }
+            DbPool::MySql(mysql_pool) => {
+                mysql_pool
+                    .execute_query_streaming(query, limit, offset)
+                    .await
+            }
+            DbPool::Sqlite(sqlite_pool) => {
+                sqlite_pool
+                    .execute_query_streaming(query, limit, offset)
+                    .await
+            }
+            DbPool::Clickhouse(clickhouse_pool) => {
+                clickhouse_pool
+                    .execute_query_streaming(query, limit, offset)
+                    .await
+            }
+            DbPool::Mssql(mssql_pool) => {
+                mssql_pool
+                    .execute_query_streaming(query, limit, offset)
+                    .await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlparser::dialect::PostgreSqlDialect;
+
+    #[test]
+    fn test_max_limit_caps_explicit_limit() {
+        let sanitized = sanitize_select(
+            &GenericDialect {},
+            "SELECT * FROM t LIMIT 1000",
+            10,
+            None,
+            100,
+            &TableAccess::default(),
+        )
+        .unwrap();
+        assert_eq!(sanitized, "SELECT * FROM t LIMIT 100");
+    }
+
+    #[test]
+    fn test_non_numeric_limit() {
+        let sanitize = |query| {
+            sanitize_select(
+                &PostgreSqlDialect {},
+                query,
+                10,
+                None,
+                100,
+                &TableAccess::default(),
+            )
+        };
+        assert_eq!(
+            sanitize("SELECT * FROM t LIMIT ALL").unwrap(),
+            "SELECT * FROM t LIMIT 10"
+        );
+        for query in ["SELECT * FROM t LIMIT $1", "SELECT * FROM t LIMIT 5 + 5"] {
+            let result = sanitize(query);
+            assert!(matches!(result, Err(AppError::BadRequest(_))), "{}", query);
+        }
+    }
+
+    #[test]
+    fn test_writable_cte_rejected() {
+        let sanitize = |query| {
+            sanitize_select(
+                &PostgreSqlDialect {},
+                query,
+                10,
+                None,
+                100,
+                &TableAccess::default(),
+            )
+        };
+        for query in [
+            "WITH d AS (UPDATE users SET name = 'x' RETURNING *) SELECT * FROM d",
+            "WITH d AS (INSERT INTO users (id) VALUES (1) RETURNING *) SELECT * FROM d",
+            "WITH a AS (SELECT 1), d AS (UPDATE users SET id = 2 RETURNING id) SELECT * FROM d",
+        ] {
+            let result = sanitize(query);
+            assert!(matches!(result, Err(AppError::BadRequest(_))), "{}", query);
+        }
+        // sqlparser can't parse a DELETE in a CTE, so it never gets that far
+        let result = sanitize("WITH d AS (DELETE FROM users RETURNING *) SELECT * FROM d");
+        assert!(matches!(result, Err(AppError::SqlParsingError { .. })));
+        assert!(sanitize("WITH a AS (SELECT 1 AS n) SELECT n FROM a").is_ok());
+    }
+
+    #[test]
+    fn test_query_too_long() {
+        let query = format!("SELECT '{}'", "x".repeat(MAX_QUERY_LEN));
+        let err = sanitize_select(
+            &GenericDialect {},
+            &query,
+            10,
+            None,
+            MAX_LIMIT,
+            &TableAccess::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, AppError::BadRequest(msg) if msg.contains("maximum")));
+        assert!(sanitize_dml(&GenericDialect {}, &query, &TableAccess::default()).is_err());
+    }
+
+    #[test]
+    fn test_check_params() {
+        let two = [Value::from(1), Value::from("a")];
+        assert!(check_params("SELECT $1, $2, $1", &two).is_ok());
+        assert!(check_params("SELECT ? WHERE x = ?", &two).is_ok());
+        assert!(check_params("SELECT '?', $1", &two[..1]).is_ok());
+        let err = check_params("SELECT $3", &two).unwrap_err();
+        assert!(matches!(err, AppError::BadRequest(msg) if msg.contains("expects 3")));
+    }
+
+    #[test]
+    fn test_apply_template_vars() {
+        let vars: HashMap<String, String> = [
+            ("table", "archive.orders_2024"),
+            ("column", "created_at"),
+            ("date_from", "2024-01-01"),
+            ("min_total", "10.5"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        let query = "SELECT {{column}} FROM {{ table }} \
+                     WHERE {{column}} >= {{date_from}} AND total > {{min_total}}";
+        let (sql, literals) = apply_template_vars(query, &vars, &Map::new()).unwrap();
+        assert_eq!(
+            sql,
+            "SELECT created_at FROM archive.orders_2024 \
+             WHERE created_at >= :date_from AND total > :min_total"
+        );
+        assert_eq!(
+            Value::Object(literals),
+            serde_json::json!({ "date_from": "2024-01-01", "min_total": 10.5 })
+        );
+
+        // Values are never pasted unless they are identifiers
+        let vars = HashMap::from([("t".to_string(), "users; DROP TABLE users".to_string())]);
+        let (sql, literals) =
+            apply_template_vars("SELECT * FROM {{t}}", &vars, &Map::new()).unwrap();
+        assert_eq!(sql, "SELECT * FROM :t");
+        assert_eq!(literals["t"], "users; DROP TABLE users");
+
+        for query in [
+            "SELECT {{missing}}",
+            "SELECT 1",
+            "SELECT {{t",
+            "SELECT {{t;}}",
+        ] {
+            let result = apply_template_vars(query, &vars, &Map::new());
+            assert!(matches!(result, Err(AppError::BadRequest(_))), "{}", query);
+        }
+        // Without values nothing is templated
+        let (sql, _) = apply_template_vars("SELECT '{{x}}'", &HashMap::new(), &Map::new()).unwrap();
+        assert_eq!(sql, "SELECT '{{x}}'");
+    }
+
+    #[test]
+    fn test_bind_named_params() {
+        let named: Map<String, Value> =
+            serde_json::from_value(serde_json::json!({ "id": 7, "name": "a" })).unwrap();
+        let query = "SELECT * FROM t\nWHERE id = :id OR parent = :id AND name <> ':id' || :name";
+
+        let (sql, values) =
+            bind_named_params(query, &[], &named, PlaceholderStyle::Dollar).unwrap();
+        assert_eq!(
+            sql,
+            "SELECT * FROM t\nWHERE id = $1 OR parent = $1 AND name <> ':id' || $2"
+        );
+        assert_eq!(values, [Value::from(7), Value::from("a")]);
+
+        let (sql, values) =
+            bind_named_params(query, &[], &named, PlaceholderStyle::Question).unwrap();
+        assert_eq!(
+            sql,
+            "SELECT * FROM t\nWHERE id = ? OR parent = ? AND name <> ':id' || ?"
+        );
+        assert_eq!(values, [Value::from(7), Value::from(7), Value::from("a")]);
+
+        // Casts and slices are left alone
+        let (sql, values) = bind_named_params(
+            "SELECT a::int, b[1:2] FROM t",
+            &[],
+            &Map::new(),
+            PlaceholderStyle::Dollar,
+        )
+        .unwrap();
+        assert_eq!(sql, "SELECT a::int, b[1:2] FROM t");
+        assert!(values.is_empty());
+
+        let unfilled = bind_named_params(
+            "SELECT :id, :name, :other",
+            &[],
+            &named,
+            PlaceholderStyle::Dollar,
+        );
+        assert!(matches!(unfilled, Err(AppError::BadRequest(msg)) if msg.contains(":other")));
+        let unknown = bind_named_params("SELECT :id", &[], &named, PlaceholderStyle::Dollar);
+        assert!(matches!(unknown, Err(AppError::BadRequest(msg)) if msg.contains(":name")));
+        let mixed = bind_named_params("SELECT :id, $1", &[], &named, PlaceholderStyle::Dollar);
+        assert!(matches!(mixed, Err(AppError::BadRequest(_))));
+    }
+
+    #[test]
+    fn test_unknown_types_fall_back() {
+        assert_eq!(
+            ColumnType::from("hyperloglog".to_string()),
+            ColumnType::Other("hyperloglog".to_string())
+        );
+        assert_eq!(
+            ColumnType::from(String::new()),
+            ColumnType::Other(String::new())
+        );
+        assert_eq!(TableType::from("sequence".to_string()), TableType::Table);
+        assert_eq!(TableType::from("S".to_string()), TableType::Table);
+        assert_eq!(
+            TableType::from("partitioned_table".to_string()),
+            TableType::PartitionedTable
+        );
+    }
+
+    #[test]
+    fn test_column_type_mysql_spellings() {
+        let cases = [
+            ("tinyint", ColumnType::SmallInt),
+            ("tinyint unsigned", ColumnType::SmallInt),
+            ("mediumint", ColumnType::Integer),
+            ("int unsigned zerofill", ColumnType::Integer),
+            ("double", ColumnType::DoublePrecision),
+            ("float unsigned", ColumnType::Real),
+            ("longtext", ColumnType::Text),
+            ("mediumtext", ColumnType::Text),
+            ("varbinary", ColumnType::Bytea),
+            ("longblob", ColumnType::Bytea),
+            ("datetime", ColumnType::Timestamp),
+            ("enum", ColumnType::Enum),
+            ("set", ColumnType::Set),
+            ("year", ColumnType::Year),
+            ("geometry", ColumnType::Other("geometry".to_string())),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(ColumnType::from_str(input).unwrap(), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn test_parse_error_position() {
+        let query = "SELECT id\nFROM users WHERE id = = 1";
+        let Err(AppError::SqlParsingError {
+            message,
+            line,
+            column,
+            near,
+        }) = sanitize_select(
+            &GenericDialect {},
+            query,
+            10,
+            None,
+            MAX_LIMIT,
+            &TableAccess::default(),
+        )
+        else {
+            panic!("Expected a SQL parsing error");
+        };
+        assert_eq!(message, "Expected: an expression, found: =");
+        assert_eq!(line, Some(2));
+        assert_eq!(column, Some(23));
+        assert_eq!(near.as_deref(), Some("= 1"));
+    }
 }
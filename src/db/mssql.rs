@@ -0,0 +1,845 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use super::{
+    ColumnInfo, ColumnType, MssqlPoolHandler, MutationResult, PoolHandler, PoolStats, QueryResult,
+    RowLimits, RowStream, StatementResult, TableAccess, TableFilter, TableInfo, TableSchema,
+};
+use crate::{
+    config::DatabaseConfig,
+    db::{BACKEND_PID, check_query_len, number_expr, parse_error},
+    error::AppError,
+};
+use async_stream::try_stream;
+use futures::TryStreamExt;
+use serde_json::{Map, Number, Value};
+use sqlparser::{
+    ast,
+    dialect::{Dialect, MsSqlDialect},
+    parser::Parser,
+};
+use tiberius::{
+    Client, ColumnData, Config, FromSql, Row, ToSql,
+    time::time::{Date, OffsetDateTime, PrimitiveDateTime, Time},
+};
+use time::{
+    format_description::{BorrowedFormatItem, well_known::Rfc3339},
+    macros::format_description,
+};
+use tokio::net::TcpStream;
+use tokio_util::compat::{Compat, TokioAsyncWriteCompatExt};
+use tracing::info;
+
+/// Most connections the pool opens, as for the sqlx pools
+const MAX_CONNECTIONS: u32 = 5;
+
+const DATE_FORMAT: &[BorrowedFormatItem<'_>] = format_description!("[year]-[month]-[day]");
+const TIME_FORMAT: &[BorrowedFormatItem<'_>] =
+    format_description!("[hour]:[minute]:[second].[subsecond]");
+const DATE_TIME_FORMAT: &[BorrowedFormatItem<'_>] =
+    format_description!("[year]-[month]-[day]T[hour]:[minute]:[second].[subsecond]");
+
+pub type MssqlPool = bb8::Pool<ConnectionManager>;
+
+/// Opens tiberius connections for the bb8 pool
+pub struct ConnectionManager {
+    config: Config,
+}
+
+impl bb8::ManageConnection for ConnectionManager {
+    type Connection = Client<Compat<TcpStream>>;
+    type Error = tiberius::error::Error;
+
+    async fn connect(&self) -> Result<Self::Connection, Self::Error> {
+        let tcp = TcpStream::connect(self.config.get_addr()).await?;
+        tcp.set_nodelay(true)?;
+        Client::connect(self.config.clone(), tcp.compat_write()).await
+    }
+
+    async fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        conn.simple_query("SELECT 1").await?.into_row().await?;
+        Ok(())
+    }
+
+    fn has_broken(&self, _conn: &mut Self::Connection) -> bool {
+        false
+    }
+}
+
+// Manual Debug implementation to keep the password out of logs
+impl fmt::Debug for ConnectionManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnectionManager")
+            .field("addr", &self.config.get_addr())
+            .finish_non_exhaustive()
+    }
+}
+
+impl PoolHandler for MssqlPoolHandler {
+    async fn try_new(db_config: &DatabaseConfig) -> Result<Self, AppError> {
+        let config = parse_conn_string(&db_config.conn_string)?;
+        let pool = bb8::Pool::builder()
+            .max_size(MAX_CONNECTIONS)
+            .build(ConnectionManager { config })
+            .await
+            .map_err(mssql_error)?;
+        let handler = MssqlPoolHandler(
+            pool,
+            RowLimits::new(db_config),
+            TableAccess::new(db_config),
+            db_config.exact_numeric,
+        );
+        // Fail at startup, like the sqlx pools, if the server is unreachable
+        handler.health_check().await?;
+        Ok(handler)
+    }
+
+    fn limits(&self) -> RowLimits {
+        self.1
+    }
+
+    fn access(&self) -> &TableAccess {
+        &self.2
+    }
+
+    fn dialect(&self) -> Box<dyn Dialect> {
+        Box::new(MsSqlDialect {})
+    }
+
+    fn quote_identifier(&self, ident: &str) -> String {
+        format!("[{}]", ident.replace(']', "]]"))
+    }
+
+    async fn list_tables(&self, filter: &TableFilter) -> Result<Vec<TableInfo>, AppError> {
+        let limit = filter.limit.map_or(i64::MAX, |n| n as i64);
+        let offset = filter.offset.unwrap_or(0) as i64;
+        let rows = self
+            .query_rows(
+                r#"
+            SELECT t.TABLE_SCHEMA + '.' + t.TABLE_NAME AS name,
+              CASE t.TABLE_TYPE WHEN 'VIEW' THEN 'view' ELSE 'table' END AS type,
+              p.row_estimate
+            FROM INFORMATION_SCHEMA.TABLES t
+            LEFT JOIN (
+              SELECT object_id, SUM(rows) AS row_estimate
+              FROM sys.partitions
+              WHERE index_id IN (0, 1) -- heap or clustered index
+              GROUP BY object_id
+            ) p ON p.object_id = OBJECT_ID(QUOTENAME(t.TABLE_SCHEMA) + '.' + QUOTENAME(t.TABLE_NAME))
+            WHERE t.TABLE_SCHEMA NOT IN ('sys', 'INFORMATION_SCHEMA')
+              AND (@P1 IS NULL OR t.TABLE_SCHEMA + '.' + t.TABLE_NAME LIKE @P1)
+              AND (@P4 IS NULL OR t.TABLE_SCHEMA = @P4)
+            ORDER BY name
+            OFFSET @P2 ROWS FETCH NEXT @P3 ROWS ONLY"#,
+                &[
+                    &filter.name_filter.as_deref(),
+                    &offset,
+                    &limit,
+                    &filter.schema.as_deref(),
+                ],
+            )
+            .await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(TableInfo {
+                    name: get_string(row, "name")?,
+                    table_type: get_string(row, "type")?.into(),
+                    row_estimate: if filter.include_estimates {
+                        row.try_get("row_estimate").map_err(mssql_error)?
+                    } else {
+                        None
+                    },
+                    description: None,
+                })
+            })
+            .collect()
+    }
+
+    async fn list_schemas(&self) -> Result<Vec<String>, AppError> {
+        let rows = self
+            .query_rows(
+                r#"
+            SELECT DISTINCT TABLE_SCHEMA
+            FROM INFORMATION_SCHEMA.TABLES
+            WHERE TABLE_SCHEMA NOT IN ('sys', 'INFORMATION_SCHEMA')
+            ORDER BY TABLE_SCHEMA"#,
+                &[],
+            )
+            .await?;
+        rows.iter()
+            .map(|row| get_string(row, "TABLE_SCHEMA"))
+            .collect()
+    }
+
+    async fn get_table_schema(&self, table_name: &str) -> Result<TableSchema, AppError> {
+        // Unqualified names are looked up in the user's default schema
+        let (schema, table) = match table_name.split_once('.') {
+            Some((schema, table)) => (Some(schema), table),
+            None => (None, table_name),
+        };
+        let params: [&dyn ToSql; 2] = [&schema, &table];
+
+        // 1. Fetch basic column info
+        let raw_columns = self
+            .query_rows(
+                r#"
+            SELECT COLUMN_NAME, DATA_TYPE, IS_NULLABLE
+            FROM INFORMATION_SCHEMA.COLUMNS
+            WHERE TABLE_SCHEMA = COALESCE(@P1, SCHEMA_NAME()) AND TABLE_NAME = @P2
+            ORDER BY ORDINAL_POSITION"#,
+                &params,
+            )
+            .await?;
+
+        // 2. Fetch PK/Unique constraints
+        let constraints = self
+            .query_rows(
+                r#"
+            SELECT kcu.COLUMN_NAME, tc.CONSTRAINT_TYPE
+            FROM INFORMATION_SCHEMA.TABLE_CONSTRAINTS tc
+            JOIN INFORMATION_SCHEMA.KEY_COLUMN_USAGE kcu
+              ON tc.CONSTRAINT_SCHEMA = kcu.CONSTRAINT_SCHEMA
+             AND tc.CONSTRAINT_NAME = kcu.CONSTRAINT_NAME
+            WHERE tc.CONSTRAINT_TYPE IN ('PRIMARY KEY', 'UNIQUE')
+              AND tc.TABLE_SCHEMA = COALESCE(@P1, SCHEMA_NAME()) AND tc.TABLE_NAME = @P2"#,
+                &params,
+            )
+            .await?;
+        let mut pk_columns = HashMap::new();
+        let mut unique_columns = HashMap::new();
+        for row in &constraints {
+            let column = get_string(row, "COLUMN_NAME")?;
+            if get_string(row, "CONSTRAINT_TYPE")? == "PRIMARY KEY" {
+                pk_columns.insert(column.clone(), true);
+            }
+            unique_columns.insert(column, true); // PKs are implicitly unique
+        }
+
+        // 3. Fetch Foreign Key constraints, pairing each referencing column
+        // with the referenced one at the same position
+        let foreign_keys = self
+            .query_rows(
+                r#"
+            SELECT kcu.COLUMN_NAME, ref.TABLE_NAME AS foreign_table_name,
+              ref.COLUMN_NAME AS foreign_column_name
+            FROM INFORMATION_SCHEMA.REFERENTIAL_CONSTRAINTS rc
+            JOIN INFORMATION_SCHEMA.KEY_COLUMN_USAGE kcu
+              ON kcu.CONSTRAINT_SCHEMA = rc.CONSTRAINT_SCHEMA
+             AND kcu.CONSTRAINT_NAME = rc.CONSTRAINT_NAME
+            JOIN INFORMATION_SCHEMA.KEY_COLUMN_USAGE ref
+              ON ref.CONSTRAINT_SCHEMA = rc.UNIQUE_CONSTRAINT_SCHEMA
+             AND ref.CONSTRAINT_NAME = rc.UNIQUE_CONSTRAINT_NAME
+             AND ref.ORDINAL_POSITION = kcu.ORDINAL_POSITION
+            WHERE kcu.TABLE_SCHEMA = COALESCE(@P1, SCHEMA_NAME()) AND kcu.TABLE_NAME = @P2"#,
+                &params,
+            )
+            .await?;
+        let mut fk_map = HashMap::new();
+        for row in &foreign_keys {
+            fk_map.insert(
+                get_string(row, "COLUMN_NAME")?,
+                (
+                    get_string(row, "foreign_table_name")?,
+                    get_string(row, "foreign_column_name")?,
+                ),
+            );
+        }
+
+        // 4. Combine all info
+        let columns = raw_columns
+            .iter()
+            .map(|row| {
+                let name = get_string(row, "COLUMN_NAME")?;
+                let fk_info = fk_map.get(&name);
+                Ok(ColumnInfo {
+                    data_type: column_type(&get_string(row, "DATA_TYPE")?),
+                    is_nullable: get_string(row, "IS_NULLABLE")? == "YES",
+                    is_pk: pk_columns.contains_key(&name),
+                    is_unique: unique_columns.contains_key(&name),
+                    fk_table: fk_info.map(|(t, _)| t.clone()),
+                    fk_column: fk_info.map(|(_, c)| c.clone()),
+                    stats: None,
+                    enum_values: None,
+                    description: None,
+                    name,
+                })
+            })
+            .collect::<Result<Vec<_>, AppError>>()?;
+
+        Ok(TableSchema {
+            table_name: table_name.to_string(),
+            description: None,
+            columns,
+        })
+    }
+
+    async fn sanitize_query(
+        &self,
+        query: &str,
+        limit: usize,
+        offset: Option<usize>,
+    ) -> Result<String, AppError> {
+        sanitize_select(query, limit, offset, self.limits().max, self.access())
+    }
+
+    async fn execute_query(
+        &self,
+        query: &str,
+        params: &[Value],
+        limit: Option<usize>,
+        offset: Option<usize>,
+        analyze: bool,
+    ) -> Result<QueryResult, AppError> {
+        if analyze {
+            return Err(AppError::BadRequest(
+                "EXPLAIN ANALYZE is only supported on Postgres".to_string(),
+            ));
+        }
+        // SQL Server numbers its placeholders @P1, @P2, ..., which the
+        // parameter checks don't know
+        if !params.is_empty() {
+            return Err(AppError::BadRequest(
+                "Bind parameters are not supported on SQL Server".to_string(),
+            ));
+        }
+
+        // 1. Get the original, validated SQL string
+        let limit = self.limits().effective(limit);
+        let sanitized_sql = self.sanitize_query(query, limit, offset).await?;
+        info!("Sanitized query: {}", sanitized_sql);
+
+        // 2. Run on one connection, so a cancellable query can be stopped
+        // server-side by its session id
+        let mut conn = self.0.get().await.map_err(mssql_error)?;
+        if let Ok(slot) = BACKEND_PID.try_with(Arc::clone) {
+            let row = conn
+                .simple_query("SELECT CAST(@@SPID AS int)")
+                .await
+                .map_err(mssql_error)?
+                .into_row()
+                .await
+                .map_err(mssql_error)?;
+            if let Some(spid) = row.and_then(|row| row.get::<i32, _>(0)) {
+                let _ = slot.set(spid);
+            }
+        }
+
+        // 3. Execute actual query and time it. SQL Server plans are XML, so
+        // none is returned.
+        let start_time = Instant::now();
+        let rows = conn
+            .simple_query(sanitized_sql)
+            .await
+            .map_err(mssql_error)?
+            .into_first_result()
+            .await
+            .map_err(mssql_error)?;
+        let execution_time = start_time.elapsed();
+
+        let data = rows
+            .iter()
+            .map(|row| row_to_json(row, self.exact_numeric()))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(QueryResult {
+            data: Value::Array(data),
+            execution_time,
+            plan: None,
+            warnings: Vec::new(),
+        })
+    }
+
+    async fn execute_mutation(&self, query: &str) -> Result<MutationResult, AppError> {
+        let sanitized_sql = self.sanitize_mutation(query).await?;
+        info!("Sanitized mutation: {}", sanitized_sql);
+
+        let start_time = Instant::now();
+        let mut conn = self.0.get().await.map_err(mssql_error)?;
+        let result = conn
+            .execute(sanitized_sql, &[])
+            .await
+            .map_err(mssql_error)?;
+        Ok(MutationResult {
+            affected_rows: result.total(),
+            execution_time: start_time.elapsed(),
+        })
+    }
+
+    async fn execute_batch(
+        &self,
+        _statements: &[String],
+        _limit: Option<usize>,
+        _read_only: bool,
+    ) -> Result<Vec<StatementResult>, AppError> {
+        Err(AppError::NotImplemented(
+            "Batches are not supported on SQL Server yet".to_string(),
+        ))
+    }
+
+    async fn health_check(&self) -> Result<Duration, AppError> {
+        let start_time = Instant::now();
+        let mut conn = self.0.get().await.map_err(mssql_error)?;
+        conn.simple_query("SELECT 1")
+            .await
+            .map_err(mssql_error)?
+            .into_row()
+            .await
+            .map_err(mssql_error)?;
+        Ok(start_time.elapsed())
+    }
+
+    fn pool_stats(&self) -> PoolStats {
+        let state = self.0.state();
+        PoolStats {
+            size: Some(state.connections),
+            idle: Some(state.idle_connections),
+            in_use: Some(state.connections.saturating_sub(state.idle_connections)),
+            max_size: Some(MAX_CONNECTIONS),
+        }
+    }
+
+    async fn close(&self) {
+        // Nothing to do: bb8 closes the connections when the pool is dropped
+    }
+
+    async fn cancel_backend(&self, pid: i32) -> Result<(), AppError> {
+        // SQL Server can't stop just the statement, so the session goes;
+        // the pool drops the dead connection on its next checkout
+        info!("Killing SQL Server session {}", pid);
+        let mut conn = self.0.get().await.map_err(mssql_error)?;
+        conn.execute(format!("KILL {}", pid), &[])
+            .await
+            .map_err(mssql_error)?;
+        Ok(())
+    }
+
+    async fn execute_query_streaming(
+        &self,
+        query: &str,
+        limit: Option<usize>,
+        offset: Option<usize>,
+    ) -> Result<RowStream, AppError> {
+        let limit = self.limits().effective(limit);
+        let sanitized_sql = self.sanitize_query(query, limit, offset).await?;
+        info!("Sanitized streaming query: {}", sanitized_sql);
+
+        let pool = self.0.clone();
+        let exact_numeric = self.exact_numeric();
+        Ok(Box::pin(try_stream! {
+            let mut conn = pool.get_owned().await.map_err(mssql_error)?;
+            let mut rows = conn
+                .simple_query(sanitized_sql)
+                .await
+                .map_err(mssql_error)?
+                .into_row_stream();
+            while let Some(row) = rows.try_next().await.map_err(mssql_error)? {
+                yield row_to_json(&row, exact_numeric)?;
+            }
+        }))
+    }
+}
+
+impl MssqlPoolHandler {
+    fn exact_numeric(&self) -> bool {
+        self.3
+    }
+
+    /// Run a query with `@P1`, `@P2`, ... placeholders and return its rows
+    async fn query_rows(&self, sql: &str, params: &[&dyn ToSql]) -> Result<Vec<Row>, AppError> {
+        let mut conn = self.0.get().await.map_err(mssql_error)?;
+        conn.query(sql, params)
+            .await
+            .map_err(mssql_error)?
+            .into_first_result()
+            .await
+            .map_err(mssql_error)
+    }
+}
+
+/// Parse an ADO.NET connection string (`server=tcp:host,1433;database=...;
+/// user=...;password=...`), or a JDBC one (`jdbc:sqlserver://...`)
+fn parse_conn_string(conn_string: &str) -> Result<Config, AppError> {
+    let config = if conn_string.starts_with("jdbc:") {
+        Config::from_jdbc_string(conn_string)
+    } else {
+        Config::from_ado_string(conn_string)
+    };
+    config.map_err(|e| AppError::Mssql(format!("invalid connection string: {}", e)))
+}
+
+fn mssql_error(e: impl fmt::Display) -> AppError {
+    AppError::Mssql(e.to_string())
+}
+
+/// A text column of a metadata row; NULL reads as an empty string
+fn get_string(row: &Row, column: &str) -> Result<String, AppError> {
+    let value: Option<&str> = row.try_get(column).map_err(mssql_error)?;
+    Ok(value.unwrap_or_default().to_string())
+}
+
+/// Parse a SELECT with the SQL Server dialect and bound it with `TOP` or
+/// `OFFSET ... FETCH`, which SQL Server has instead of `LIMIT`; see
+/// `PoolHandler::sanitize_query`. A query without either gets `TOP`, unless
+/// it is ordered or paged: `FETCH` needs an `ORDER BY`, so a paged query
+/// without one is ordered by `(SELECT NULL)`.
+fn sanitize_select(
+    query: &str,
+    limit: usize,
+    offset: Option<usize>,
+    max_limit: usize,
+    access: &TableAccess,
+) -> Result<String, AppError> {
+    check_query_len(query)?;
+    let ast = Parser::parse_sql(&MsSqlDialect {}, query).map_err(|e| parse_error(query, e))?;
+    let [mut stmt] = <[ast::Statement; 1]>::try_from(ast)
+        .map_err(|_| AppError::BadRequest("Only single SQL statements are allowed".to_string()))?;
+
+    let ast::Statement::Query(ref mut query) = stmt else {
+        return Err(AppError::BadRequest(
+            "Only SELECT queries are allowed".to_string(),
+        ));
+    };
+    if !matches!(
+        *query.body,
+        ast::SetExpr::Select(_) | ast::SetExpr::Query(_)
+    ) {
+        return Err(AppError::BadRequest(
+            "Only SELECT-like queries are allowed.".to_string(),
+        ));
+    }
+    if query.limit.is_some() {
+        return Err(AppError::BadRequest(
+            "SQL Server has no LIMIT; use TOP or OFFSET ... FETCH instead".to_string(),
+        ));
+    }
+    // A row count kept if below the requested limit, else capped at the max
+    let bound = |existing: usize| {
+        if existing < limit {
+            existing
+        } else {
+            existing.min(max_limit)
+        }
+    };
+
+    let top = match &mut *query.body {
+        ast::SetExpr::Select(select) => select.top.as_mut(),
+        _ => None,
+    };
+    if let Some(top) = top {
+        if offset.is_some() {
+            return Err(AppError::BadRequest(
+                "TOP can't be paged; use ORDER BY ... OFFSET ... FETCH instead".to_string(),
+            ));
+        }
+        let count = match &top.quantity {
+            Some(ast::TopQuantity::Constant(n)) if !top.percent => bound(*n as usize),
+            // Expressions and percentages are replaced with the limit
+            _ => limit,
+        };
+        top.quantity = Some(ast::TopQuantity::Constant(count as u64));
+        top.percent = false;
+    } else if query.fetch.is_none() && query.order_by.is_none() && offset.is_none() {
+        if let ast::SetExpr::Select(select) = &mut *query.body {
+            select.top = Some(ast::Top {
+                with_ties: false,
+                percent: false,
+                quantity: Some(ast::TopQuantity::Constant(limit as u64)),
+            });
+        } else {
+            page(query, limit, offset, bound);
+        }
+    } else {
+        page(query, limit, offset, bound);
+    }
+
+    access.check_statement(&stmt)?;
+    Ok(stmt.to_string())
+}
+
+/// Bound a query with `OFFSET ... FETCH`, capping an existing `FETCH` and
+/// keeping the larger of an existing and the requested offset
+fn page(
+    query: &mut ast::Query,
+    limit: usize,
+    offset: Option<usize>,
+    bound: impl Fn(usize) -> usize,
+) {
+    if query.order_by.is_none() {
+        query.order_by = Some(arbitrary_order());
+    }
+
+    let existing_offset = match &query.offset {
+        Some(ast::Offset {
+            value:
+                ast::Expr::Value(ast::ValueWithSpan {
+                    value: ast::Value::Number(s, _),
+                    ..
+                }),
+            ..
+        }) => s.parse::<usize>().ok(),
+        Some(_) => None,
+        None => Some(0),
+    };
+    if let Some(existing_offset) = existing_offset {
+        query.offset = Some(ast::Offset {
+            value: number_expr(existing_offset.max(offset.unwrap_or(0))),
+            rows: ast::OffsetRows::Rows,
+        });
+    }
+
+    let count = match &query.fetch {
+        Some(ast::Fetch {
+            quantity:
+                Some(ast::Expr::Value(ast::ValueWithSpan {
+                    value: ast::Value::Number(s, _),
+                    ..
+                })),
+            percent: false,
+            ..
+        }) => bound(s.parse::<usize>().unwrap_or(0)),
+        _ => limit,
+    };
+    query.fetch = Some(ast::Fetch {
+        with_ties: false,
+        percent: false,
+        quantity: Some(number_expr(count)),
+    });
+}
+
+/// `ORDER BY (SELECT NULL)`, the usual way to page without an order
+fn arbitrary_order() -> ast::OrderBy {
+    let ast = Parser::parse_sql(&MsSqlDialect {}, "SELECT 1 ORDER BY (SELECT NULL)")
+        .expect("valid ORDER BY");
+    match ast.into_iter().next() {
+        Some(ast::Statement::Query(query)) => query.order_by.expect("has an ORDER BY"),
+        _ => unreachable!("parsed a query"),
+    }
+}
+
+/// Convert a row into a JSON object keyed by column name. Decimals become
+/// strings with `exact_numeric`, binary data a `0x` hex string.
+fn row_to_json(row: &Row, exact_numeric: bool) -> Result<Value, AppError> {
+    let mut object = Map::with_capacity(row.len());
+    for (column, data) in row.cells() {
+        object.insert(
+            column.name().to_string(),
+            cell_to_json(data, exact_numeric)?,
+        );
+    }
+    Ok(Value::Object(object))
+}
+
+fn cell_to_json(data: &ColumnData<'static>, exact_numeric: bool) -> Result<Value, AppError> {
+    let format_error = |e: time::error::Format| AppError::InvalidQueryResult(e.to_string());
+    let value = match data {
+        ColumnData::U8(n) => n.map(Value::from),
+        ColumnData::I16(n) => n.map(Value::from),
+        ColumnData::I32(n) => n.map(Value::from),
+        ColumnData::I64(n) => n.map(Value::from),
+        // Through text, so a REAL keeps its shortest representation
+        ColumnData::F32(n) => n.map(|n| float_to_json(n.is_finite(), n.to_string())),
+        ColumnData::F64(n) => n.map(|n| float_to_json(n.is_finite(), n.to_string())),
+        ColumnData::Bit(b) => b.map(Value::Bool),
+        ColumnData::String(s) => s.as_ref().map(|s| Value::String(s.to_string())),
+        ColumnData::Guid(uuid) => uuid.map(|uuid| Value::String(uuid.to_string())),
+        ColumnData::Binary(bytes) => bytes.as_ref().map(|bytes| {
+            let hex: String = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            Value::String(format!("0x{}", hex))
+        }),
+        ColumnData::Numeric(n) => n.map(|n| {
+            let text = n.to_string();
+            if exact_numeric {
+                Value::String(text)
+            } else {
+                float_to_json(true, text)
+            }
+        }),
+        ColumnData::Xml(xml) => xml.as_ref().map(|xml| Value::String(xml.to_string())),
+        ColumnData::DateTime(_) | ColumnData::SmallDateTime(_) | ColumnData::DateTime2(_) => {
+            PrimitiveDateTime::from_sql(data)
+                .map_err(mssql_error)?
+                .map(|dt| dt.format(DATE_TIME_FORMAT).map(Value::String))
+                .transpose()
+                .map_err(format_error)?
+        }
+        ColumnData::DateTimeOffset(_) => OffsetDateTime::from_sql(data)
+            .map_err(mssql_error)?
+            .map(|dt| dt.format(&Rfc3339).map(Value::String))
+            .transpose()
+            .map_err(format_error)?,
+        ColumnData::Date(_) => Date::from_sql(data)
+            .map_err(mssql_error)?
+            .map(|date| date.format(DATE_FORMAT).map(Value::String))
+            .transpose()
+            .map_err(format_error)?,
+        ColumnData::Time(_) => Time::from_sql(data)
+            .map_err(mssql_error)?
+            .map(|time| time.format(TIME_FORMAT).map(Value::String))
+            .transpose()
+            .map_err(format_error)?,
+    };
+    Ok(value.unwrap_or(Value::Null))
+}
+
+/// A number from its decimal text; NaN and infinities become null
+fn float_to_json(finite: bool, text: String) -> Value {
+    if !finite {
+        return Value::Null;
+    }
+    text.parse::<Number>()
+        .map_or(Value::String(text), Value::Number)
+}
+
+/// Map a SQL Server `DATA_TYPE` to a `ColumnType`
+fn column_type(declared: &str) -> ColumnType {
+    match declared.to_lowercase().as_str() {
+        "bit" => ColumnType::Boolean,
+        "tinyint" | "smallint" => ColumnType::SmallInt,
+        "int" => ColumnType::Integer,
+        "bigint" => ColumnType::BigInt,
+        "decimal" => ColumnType::Decimal,
+        "numeric" => ColumnType::Numeric,
+        "money" | "smallmoney" => ColumnType::Money,
+        "real" => ColumnType::Real,
+        "float" => ColumnType::DoublePrecision,
+        "char" | "nchar" => ColumnType::Char,
+        "varchar" | "nvarchar" => ColumnType::Varchar,
+        "text" | "ntext" => ColumnType::Text,
+        "binary" | "varbinary" | "image" => ColumnType::Bytea,
+        "date" => ColumnType::Date,
+        "time" => ColumnType::Time,
+        "datetime" | "datetime2" | "smalldatetime" => ColumnType::Timestamp,
+        "datetimeoffset" => ColumnType::TimestampTz,
+        "uniqueidentifier" => ColumnType::Uuid,
+        "xml" => ColumnType::Xml,
+        _ => ColumnType::Other(declared.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DatabaseType;
+
+    fn sanitize(query: &str, limit: usize, offset: Option<usize>) -> Result<String, AppError> {
+        sanitize_select(query, limit, offset, 100, &TableAccess::default())
+    }
+
+    #[test]
+    fn test_sanitize_select() {
+        assert_eq!(
+            sanitize("SELECT * FROM [dbo].[users]", 10, None).unwrap(),
+            "SELECT TOP 10 * FROM [dbo].[users]"
+        );
+        assert_eq!(
+            sanitize("SELECT DISTINCT TOP 1000 name FROM users", 10, None).unwrap(),
+            "SELECT DISTINCT TOP 100 name FROM users"
+        );
+        assert_eq!(
+            sanitize("SELECT * FROM users ORDER BY id", 10, Some(20)).unwrap(),
+            "SELECT * FROM users ORDER BY id OFFSET 20 ROWS FETCH FIRST 10 ROWS ONLY"
+        );
+        assert_eq!(
+            sanitize("SELECT * FROM users", 10, Some(20)).unwrap(),
+            "SELECT * FROM users ORDER BY (SELECT NULL) OFFSET 20 ROWS FETCH FIRST 10 ROWS ONLY"
+        );
+        assert_eq!(
+            sanitize(
+                "SELECT * FROM users ORDER BY id OFFSET 30 ROWS FETCH NEXT 500 ROWS ONLY",
+                10,
+                Some(20)
+            )
+            .unwrap(),
+            "SELECT * FROM users ORDER BY id OFFSET 30 ROWS FETCH FIRST 100 ROWS ONLY"
+        );
+
+        assert!(matches!(
+            sanitize("SELECT * FROM users LIMIT 5", 10, None),
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(matches!(
+            sanitize("SELECT TOP 5 * FROM users", 10, Some(5)),
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(matches!(
+            sanitize("DELETE FROM users", 10, None),
+            Err(AppError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_cell_to_json() {
+        let numeric = ColumnData::Numeric(Some(tiberius::numeric::Numeric::new_with_scale(
+            123456789012345678,
+            4,
+        )));
+        // Without exact_numeric a decimal is a double and loses digits
+        assert_eq!(
+            cell_to_json(&numeric, false).unwrap(),
+            Value::from(12_345_678_901_234.568)
+        );
+        assert_eq!(
+            cell_to_json(&numeric, true).unwrap(),
+            Value::String("12345678901234.5678".to_string())
+        );
+        let binary = ColumnData::Binary(Some(vec![0xde, 0xad, 0xbe, 0xef].into()));
+        assert_eq!(cell_to_json(&binary, false).unwrap(), "0xDEADBEEF");
+        assert_eq!(
+            cell_to_json(&ColumnData::F32(Some(0.1)), false)
+                .unwrap()
+                .to_string(),
+            "0.1"
+        );
+        assert_eq!(
+            cell_to_json(&ColumnData::I32(None), false).unwrap(),
+            Value::Null
+        );
+    }
+
+    #[ignore = "requires a running SQL Server instance"]
+    #[tokio::test]
+    async fn test_execute_query() {
+        let db = MssqlPoolHandler::try_new(&get_db_config()).await.unwrap();
+        let result = db
+            .execute_query(
+                "SELECT n FROM (VALUES (1), (2), (3), (4)) t(n) ORDER BY n",
+                &[],
+                Some(3),
+                None,
+                false,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            result.data,
+            serde_json::json!([{ "n": 1 }, { "n": 2 }, { "n": 3 }])
+        );
+    }
+
+    fn get_db_config() -> DatabaseConfig {
+        DatabaseConfig {
+            name: "test".to_string(),
+            db_type: DatabaseType::Mssql,
+            conn_string:
+                "server=tcp:localhost,1433;user=sa;password=Passw0rd!;TrustServerCertificate=true"
+                    .to_string(),
+            query_timeout_secs: None,
+            max_concurrent_queries: None,
+            read_only: true,
+            default_limit: None,
+            max_limit: None,
+            ssl_mode: None,
+            ssl_root_cert: None,
+            allowed_tables: None,
+            denied_tables: Vec::new(),
+            masked_columns: Vec::new(),
+            required_roles: Vec::new(),
+            exact_numeric: false,
+            statement_cache_capacity: None,
+        }
+    }
+}
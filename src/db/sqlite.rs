@@ -0,0 +1,686 @@
+use std::{
+    collections::HashMap,
+    ops::Deref,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use super::{
+    BatchStatement, ColumnInfo, ColumnType, MutationResult, PoolHandler, PoolStats, QueryResult,
+    RowLimits, RowStream, SqlitePoolHandler, StatementResult, TableAccess, TableFilter, TableInfo,
+    TableSchema,
+};
+use crate::{
+    config::DatabaseConfig,
+    db::{bind_params, check_params, sanitize_batch},
+    error::AppError,
+};
+use async_stream::try_stream;
+use futures::TryStreamExt;
+use serde_json::{Map, Number, Value};
+use sqlparser::dialect::{Dialect, SQLiteDialect};
+use sqlx::{
+    Column, Row, Sqlite, SqliteConnection, SqlitePool, TypeInfo, ValueRef,
+    sqlite::{SqlitePoolOptions, SqliteRow},
+};
+use tracing::{info, warn};
+
+// Row from `pragma_table_info`
+#[derive(sqlx::FromRow)]
+struct RawColumnInfo {
+    name: String,
+    #[sqlx(rename = "type")]
+    data_type: String, // Declared type, e.g. "VARCHAR(255)"
+    notnull: bool,
+    pk: i64, // 1-based position in the primary key, 0 if not part of it
+}
+
+// Row from `pragma_foreign_key_list`
+#[derive(sqlx::FromRow)]
+struct ForeignKeyInfoRow {
+    column_name: String,
+    foreign_table_name: String,
+    foreign_column_name: Option<String>, // NULL when referencing the implicit PK
+}
+
+impl PoolHandler for SqlitePoolHandler {
+    async fn try_new(db_config: &DatabaseConfig) -> Result<Self, AppError> {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(5)
+            .connect(&db_config.conn_string)
+            .await?;
+        Ok(SqlitePoolHandler(
+            pool,
+            RowLimits::new(db_config),
+            TableAccess::new(db_config),
+        ))
+    }
+
+    fn limits(&self) -> RowLimits {
+        self.1
+    }
+
+    fn access(&self) -> &TableAccess {
+        &self.2
+    }
+
+    fn dialect(&self) -> Box<dyn Dialect> {
+        Box::new(SQLiteDialect {})
+    }
+
+    // `list_tables` names are unqualified, and may contain dots
+    fn quote_table_name(&self, table_name: &str) -> String {
+        self.quote_identifier(table_name)
+    }
+
+    async fn list_tables(&self, filter: &TableFilter) -> Result<Vec<TableInfo>, AppError> {
+        let tables = sqlx::query_as::<sqlx::Sqlite, TableInfo>(
+            r#"
+            SELECT name, type
+            FROM sqlite_master
+            WHERE type IN ('table', 'view')
+              AND name NOT LIKE 'sqlite\_%' ESCAPE '\'
+              AND name NOT LIKE '\_%' ESCAPE '\'
+              AND (?1 IS NULL OR name LIKE ?1)
+              AND (?4 IS NULL OR ?4 = 'main')
+            ORDER BY name
+            LIMIT ?2 OFFSET ?3"#,
+        )
+        .bind(filter.name_filter.as_deref())
+        .bind(filter.limit.map_or(-1, |n| n as i64)) // LIMIT -1 means no limit
+        .bind(filter.offset.unwrap_or(0) as i64)
+        .bind(filter.schema.as_deref())
+        .fetch_all(&self.0)
+        .await?;
+        Ok(tables)
+    }
+
+    async fn list_schemas(&self) -> Result<Vec<String>, AppError> {
+        // Attached databases are never listed, so every table is in `main`
+        Ok(vec!["main".to_string()])
+    }
+
+    async fn get_table_schema(&self, table_name: &str) -> Result<TableSchema, AppError> {
+        // 1. Fetch basic column info (PRAGMA table-valued functions accept binds)
+        let raw_columns = sqlx::query_as::<_, RawColumnInfo>(
+            r#"SELECT name, type, "notnull", pk FROM pragma_table_info(?) ORDER BY cid"#,
+        )
+        .bind(table_name)
+        .fetch_all(&self.0)
+        .await?;
+
+        // 2. Fetch columns covered by unique indexes (includes the PK index, if any)
+        let unique_columns: Vec<String> = sqlx::query_scalar(
+            r#"SELECT ii.name
+             FROM pragma_index_list(?) AS il
+             JOIN pragma_index_info(il.name) AS ii
+             WHERE il."unique" = 1"#,
+        )
+        .bind(table_name)
+        .fetch_all(&self.0)
+        .await?;
+
+        // 3. Fetch Foreign Key constraints
+        let foreign_keys = sqlx::query_as::<_, ForeignKeyInfoRow>(
+            r#"SELECT "from" AS column_name,
+                    "table" AS foreign_table_name,
+                    "to" AS foreign_column_name
+             FROM pragma_foreign_key_list(?)"#,
+        )
+        .bind(table_name)
+        .fetch_all(&self.0)
+        .await?;
+
+        // Process FKs into a map
+        let fk_map: HashMap<String, (String, Option<String>)> = foreign_keys
+            .into_iter()
+            .map(|fk| {
+                (
+                    fk.column_name,
+                    (fk.foreign_table_name, fk.foreign_column_name),
+                )
+            })
+            .collect();
+
+        // 4. Combine all info
+        let columns: Vec<ColumnInfo> = raw_columns
+            .into_iter()
+            .map(|raw| {
+                let fk_info = fk_map.get(&raw.name);
+                let is_pk = raw.pk > 0;
+                ColumnInfo {
+                    data_type: column_type(&raw.data_type),
+                    is_nullable: !raw.notnull && !is_pk,
+                    is_pk,
+                    is_unique: is_pk || unique_columns.contains(&raw.name),
+                    fk_table: fk_info.map(|(t, _)| t.clone()),
+                    fk_column: fk_info.and_then(|(_, c)| c.clone()),
+                    stats: None,
+                    enum_values: None,
+                    description: None,
+                    name: raw.name,
+                }
+            })
+            .collect();
+
+        Ok(TableSchema {
+            table_name: table_name.to_string(),
+            description: None,
+            columns,
+        })
+    }
+
+    async fn table_exists(&self, table_name: &str) -> Result<bool, AppError> {
+        let exists = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type IN ('table', 'view') AND name = ?)",
+        )
+        .bind(table_name)
+        .fetch_one(&self.0)
+        .await?;
+        Ok(exists)
+    }
+
+    async fn execute_query(
+        &self,
+        query: &str,
+        params: &[Value],
+        limit: Option<usize>,
+        offset: Option<usize>,
+        analyze: bool,
+    ) -> Result<QueryResult, AppError> {
+        if analyze {
+            return Err(AppError::BadRequest(
+                "EXPLAIN ANALYZE is only supported on Postgres".to_string(),
+            ));
+        }
+
+        // 1. Get the original, validated SQL string
+        let limit = self.limits().effective(limit);
+        let sanitized_sql = self.sanitize_query(query, limit, offset).await?;
+        info!("Sanitized query: {}", sanitized_sql);
+        check_params(&sanitized_sql, params)?;
+
+        // 2. Collect the query plan (best-effort)
+        let plan = match self.explain_query(&sanitized_sql, params).await {
+            Ok(plan) => Some(plan),
+            Err(e) => {
+                warn!("Failed to collect SQLite query plan: {}", e);
+                None
+            }
+        };
+
+        // 3. Execute actual query and time it
+        let start_time = Instant::now();
+        let rows = sqlx::query_with(&sanitized_sql, bind_params::<Sqlite>(params)?)
+            .fetch_all(&self.0)
+            .await?;
+        let execution_time = start_time.elapsed();
+
+        // 4. Convert the rows into a JSON array, matching the Postgres output
+        let data = rows
+            .iter()
+            .map(row_to_json)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(QueryResult {
+            data: Value::Array(data),
+            execution_time,
+            plan,
+            warnings: Vec::new(),
+        })
+    }
+
+    async fn execute_mutation(&self, query: &str) -> Result<MutationResult, AppError> {
+        let sanitized_sql = self.sanitize_mutation(query).await?;
+        info!("Sanitized mutation: {}", sanitized_sql);
+
+        let start_time = Instant::now();
+        let result = sqlx::query(&sanitized_sql).execute(&self.0).await?;
+        Ok(MutationResult {
+            affected_rows: result.rows_affected(),
+            execution_time: start_time.elapsed(),
+        })
+    }
+
+    async fn execute_batch(
+        &self,
+        statements: &[String],
+        limit: Option<usize>,
+        read_only: bool,
+    ) -> Result<Vec<StatementResult>, AppError> {
+        let limit = self.limits().effective(limit);
+        let statements = sanitize_batch(
+            &*self.dialect(),
+            statements,
+            limit,
+            self.limits().max,
+            read_only,
+            self.access(),
+        )?;
+
+        // Dropping the transaction without committing rolls it back
+        let mut tx = self.0.begin().await?;
+        let mut results = Vec::with_capacity(statements.len());
+        for (index, statement) in statements.iter().enumerate() {
+            let result = run_batch_statement(&mut tx, statement).await.map_err(|e| {
+                AppError::BatchStatement {
+                    index,
+                    source: Box::new(e),
+                }
+            })?;
+            results.push(result);
+        }
+        // Temporary tables would outlive the batch on the pooled connection;
+        // rolling back an unfinished batch undoes their creation instead
+        for name in statements.iter().filter_map(BatchStatement::temp_table) {
+            sqlx::query(&format!("DROP TABLE {}", name))
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(results)
+    }
+
+    async fn health_check(&self) -> Result<Duration, AppError> {
+        let start_time = Instant::now();
+        sqlx::query("SELECT 1").execute(&self.0).await?;
+        Ok(start_time.elapsed())
+    }
+
+    fn pool_stats(&self) -> PoolStats {
+        PoolStats::of(&self.0)
+    }
+
+    async fn close(&self) {
+        self.0.close().await;
+    }
+
+    async fn execute_query_streaming(
+        &self,
+        query: &str,
+        limit: Option<usize>,
+        offset: Option<usize>,
+    ) -> Result<RowStream, AppError> {
+        let limit = self.limits().effective(limit);
+        let sanitized_sql = self.sanitize_query(query, limit, offset).await?;
+        info!("Sanitized streaming query: {}", sanitized_sql);
+
+        let pool = self.0.clone();
+        Ok(Box::pin(try_stream! {
+            let mut rows = sqlx::query(&sanitized_sql).fetch(&pool);
+            while let Some(row) = rows.try_next().await? {
+                yield row_to_json(&row)?;
+            }
+        }))
+    }
+}
+
+impl SqlitePoolHandler {
+    /// Run `EXPLAIN QUERY PLAN` and return its rows as a JSON array.
+    async fn explain_query(&self, sql: &str, params: &[Value]) -> Result<Value, AppError> {
+        let explain_query = format!("EXPLAIN QUERY PLAN {}", sql);
+        let rows = sqlx::query_with(&explain_query, bind_params::<Sqlite>(params)?)
+            .fetch_all(&self.0)
+            .await?;
+        let plan = rows
+            .iter()
+            .map(row_to_json)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Value::Array(plan))
+    }
+}
+
+/// Map a declared SQLite column type (e.g. `VARCHAR(255)`) to a `ColumnType`.
+fn column_type(declared: &str) -> ColumnType {
+    let base = declared
+        .split('(')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase();
+    ColumnType::from_str(&base).unwrap_or_else(|_| ColumnType::Other(declared.to_string()))
+}
+
+/// Convert a SQLite row into a JSON object keyed by column name, based on the
+/// storage class of each value.
+fn row_to_json(row: &SqliteRow) -> Result<Value, AppError> {
+    let mut object = Map::with_capacity(row.columns().len());
+    for column in row.columns() {
+        let index = column.ordinal();
+        let raw = row.try_get_raw(index)?;
+        let value = if raw.is_null() {
+            Value::Null
+        } else {
+            match raw.type_info().name() {
+                "INTEGER" | "BOOLEAN" => {
+                    Value::Number(row.try_get_unchecked::<i64, _>(index)?.into())
+                }
+                "REAL" | "NUMERIC" => Number::from_f64(row.try_get_unchecked(index)?)
+                    .map_or(Value::Null, Value::Number),
+                "BLOB" => {
+                    let bytes: Vec<u8> = row.try_get_unchecked(index)?;
+                    Value::String(String::from_utf8_lossy(&bytes).into_owned())
+                }
+                _ => Value::String(row.try_get_unchecked(index)?),
+            }
+        };
+        object.insert(column.name().to_string(), value);
+    }
+    Ok(Value::Object(object))
+}
+
+/// Run one statement of a batch on the batch's transaction connection
+async fn run_batch_statement(
+    conn: &mut SqliteConnection,
+    statement: &BatchStatement,
+) -> Result<StatementResult, AppError> {
+    let start_time = Instant::now();
+    match statement {
+        BatchStatement::Query(sql) => {
+            let rows = sqlx::query(sql).fetch_all(&mut *conn).await?;
+            let data = rows
+                .iter()
+                .map(row_to_json)
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(StatementResult::Query(QueryResult {
+                data: Value::Array(data),
+                execution_time: start_time.elapsed(),
+                plan: None,
+                warnings: Vec::new(),
+            }))
+        }
+        BatchStatement::Mutation(sql) | BatchStatement::TempTable { sql, .. } => {
+            let result = sqlx::query(sql).execute(&mut *conn).await?;
+            Ok(StatementResult::Mutation(MutationResult {
+                affected_rows: result.rows_affected(),
+                execution_time: start_time.elapsed(),
+            }))
+        }
+    }
+}
+
+impl Deref for SqlitePoolHandler {
+    type Target = SqlitePool;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DatabaseType, db::DbPool};
+
+    #[tokio::test]
+    async fn test_list_tables() {
+        let db = get_test_db("list_tables").await;
+        let tables = db.list_tables(&TableFilter::default()).await.unwrap();
+        let names: Vec<_> = tables.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, vec!["posts", "users", "users_view"]);
+
+        let filter = TableFilter {
+            limit: Some(1),
+            offset: Some(1),
+            name_filter: Some("USERS%".to_string()),
+            include_estimates: true,
+            ..Default::default()
+        };
+        let tables = db.list_tables(&filter).await.unwrap();
+        let names: Vec<_> = tables.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, vec!["users_view"]);
+
+        // Every table is in `main`
+        assert_eq!(db.list_schemas().await.unwrap(), vec!["main"]);
+        let in_schema = |schema: &str| TableFilter {
+            schema: Some(schema.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(db.list_tables(&in_schema("main")).await.unwrap().len(), 3);
+        assert!(
+            db.list_tables(&in_schema("other"))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_table_exists() {
+        let db = get_test_db("table_exists").await;
+        assert!(db.table_exists("posts").await.unwrap());
+        assert!(db.table_exists("users_view").await.unwrap());
+        assert!(!db.table_exists("missing").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_get_table_schema() {
+        let db = get_test_db("table_schema").await;
+        let schema = db.get_table_schema("posts").await.unwrap();
+        assert_eq!(schema.table_name, "posts");
+        assert_eq!(schema.columns.len(), 3);
+
+        assert_eq!(schema.columns[0].name, "id");
+        assert_eq!(schema.columns[0].data_type, ColumnType::Integer);
+        assert!(schema.columns[0].is_pk);
+        assert!(!schema.columns[0].is_nullable);
+
+        assert_eq!(schema.columns[1].name, "user_id");
+        assert_eq!(schema.columns[1].fk_table, Some("users".to_string()));
+        assert_eq!(schema.columns[1].fk_column, Some("id".to_string()));
+
+        assert_eq!(schema.columns[2].name, "title");
+        assert_eq!(schema.columns[2].data_type, ColumnType::Varchar);
+        assert!(schema.columns[2].is_nullable);
+        assert!(schema.columns[2].is_unique);
+    }
+
+    #[tokio::test]
+    async fn test_execute_query() {
+        let db = get_test_db("execute_query").await;
+        let result = db
+            .execute_query(
+                "SELECT id, name, score FROM users ORDER BY id",
+                &[],
+                None,
+                None,
+                false,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            result.data,
+            serde_json::json!([
+                { "id": 1, "name": "Alice", "score": 1.5 },
+                { "id": 2, "name": "Bob", "score": null },
+            ])
+        );
+        assert!(result.plan.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_execute_query_with_params() {
+        let db = get_test_db("execute_query_params").await;
+        let params = [serde_json::json!("Bob")];
+        let result = db
+            .execute_query(
+                "SELECT id FROM users WHERE name = ?",
+                &params,
+                None,
+                None,
+                false,
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.data, serde_json::json!([{ "id": 2 }]));
+        assert!(result.plan.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_denied_tables() {
+        let mut db = get_test_db("denied_tables").await;
+        db.2 = TableAccess::new(&DatabaseConfig {
+            name: "test".to_string(),
+            db_type: DatabaseType::Sqlite,
+            conn_string: String::new(),
+            query_timeout_secs: None,
+            max_concurrent_queries: None,
+            read_only: true,
+            default_limit: None,
+            max_limit: None,
+            ssl_mode: None,
+            ssl_root_cert: None,
+            allowed_tables: None,
+            denied_tables: vec!["posts".to_string()],
+            masked_columns: Vec::new(),
+            required_roles: Vec::new(),
+            exact_numeric: false,
+            statement_cache_capacity: None,
+        });
+        let pool = DbPool::Sqlite(db);
+
+        let filter = TableFilter {
+            limit: Some(1),
+            offset: Some(1),
+            ..Default::default()
+        };
+        let tables = pool.list_tables(&filter).await.unwrap();
+        let names: Vec<_> = tables.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, vec!["users_view"]);
+
+        let result = pool.get_table_schema("posts").await;
+        assert!(matches!(result, Err(AppError::NotFound(_))));
+        let result = pool
+            .execute_query(
+                "SELECT * FROM users WHERE id IN (SELECT user_id FROM posts)",
+                &[],
+                None,
+                None,
+                false,
+            )
+            .await;
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn test_execute_mutation() {
+        let db = get_test_db("execute_mutation").await;
+        let result = db
+            .execute_mutation("UPDATE users SET score = 2.0 WHERE score IS NULL")
+            .await
+            .unwrap();
+        assert_eq!(result.affected_rows, 1);
+
+        let result = db.execute_mutation("DROP TABLE users").await;
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn test_execute_batch_rolls_back() {
+        let db = get_test_db("execute_batch").await;
+        let statements = vec![
+            "UPDATE users SET score = 0".to_string(),
+            "SELECT * FROM missing_table".to_string(),
+        ];
+        let result = db.execute_batch(&statements, None, false).await;
+        assert!(matches!(
+            result,
+            Err(AppError::BatchStatement { index: 1, .. })
+        ));
+
+        // The UPDATE must have been rolled back
+        let scores: Vec<Option<f64>> = sqlx::query_scalar("SELECT score FROM users ORDER BY id")
+            .fetch_all(&*db)
+            .await
+            .unwrap();
+        assert_eq!(scores, vec![Some(1.5), None]);
+    }
+
+    #[tokio::test]
+    async fn test_execute_batch_drops_temp_tables() {
+        let db = get_test_db("execute_batch_temp").await;
+        // A single connection, so every batch runs on the same one
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(db.0.connect_options().as_ref().clone())
+            .await
+            .unwrap();
+        let db = SqlitePoolHandler(pool, db.1, db.2.clone());
+        let statements = vec![
+            "CREATE TEMP TABLE batch_ids (id INT)".to_string(),
+            "SELECT id FROM users".to_string(),
+        ];
+        // A leftover table would make the next run fail with "already exists"
+        for _ in 0..3 {
+            let results = db.execute_batch(&statements, None, true).await.unwrap();
+            assert_eq!(results.len(), 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_quote_identifier() {
+        let db = get_test_db("quote_identifier").await;
+        assert_eq!(db.quote_identifier(r#"my"table"#), r#""my""table""#);
+        assert_eq!(db.quote_table_name("v1.users"), r#""v1.users""#);
+
+        sqlx::raw_sql(r#"CREATE TABLE "my""table" (id INTEGER)"#)
+            .execute(&*db)
+            .await
+            .unwrap();
+        let query = format!("SELECT * FROM {}", db.quote_identifier(r#"my"table"#));
+        assert!(
+            db.execute_query(&query, &[], None, None, false)
+                .await
+                .is_ok()
+        );
+
+        sqlx::raw_sql(r#"CREATE TABLE "v1.users" (id INTEGER)"#)
+            .execute(&*db)
+            .await
+            .unwrap();
+        let query = format!("SELECT * FROM {}", db.quote_table_name("v1.users"));
+        assert!(
+            db.execute_query(&query, &[], None, None, false)
+                .await
+                .is_ok()
+        );
+    }
+
+    async fn get_test_db(name: &str) -> SqlitePoolHandler {
+        let path = std::env::temp_dir().join(format!("r2-data2-sqlite-{}.db", name));
+        let _ = std::fs::remove_file(&path);
+        let db_config = DatabaseConfig {
+            name: "test".to_string(),
+            db_type: DatabaseType::Sqlite,
+            conn_string: format!("sqlite://{}?mode=rwc", path.display()),
+            query_timeout_secs: None,
+            max_concurrent_queries: None,
+            read_only: true,
+            default_limit: None,
+            max_limit: None,
+            ssl_mode: None,
+            ssl_root_cert: None,
+            allowed_tables: None,
+            denied_tables: Vec::new(),
+            masked_columns: Vec::new(),
+            required_roles: Vec::new(),
+            exact_numeric: false,
+            statement_cache_capacity: None,
+        };
+        let db = SqlitePoolHandler::try_new(&db_config).await.unwrap();
+        sqlx::raw_sql(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL, score REAL);
+             CREATE TABLE posts (
+               id INTEGER PRIMARY KEY,
+               user_id INTEGER NOT NULL REFERENCES users(id),
+               title VARCHAR(255) UNIQUE
+             );
+             CREATE VIEW users_view AS SELECT * FROM users;
+             INSERT INTO users (id, name, score) VALUES (1, 'Alice', 1.5), (2, 'Bob', NULL);",
+        )
+        .execute(&*db)
+        .await
+        .unwrap();
+        db
+    }
+}
@@ -15,6 +15,9 @@ pub enum AuthError {
     #[error("Missing credentials")]
     MissingCredentials,
 
+    #[error("Forbidden")]
+    Forbidden,
+
     #[error("Token creation error")]
     TokenCreation,
 
@@ -31,6 +34,16 @@ pub enum AppError {
     #[error("Database error: {0}")]
     Database(#[from] sqlx::Error),
 
+    /// A database error kept as its sanitized message, e.g. when cached
+    #[error("Database error: {0}")]
+    DatabaseMessage(String),
+
+    #[error("ClickHouse error: {0}")]
+    Clickhouse(String),
+
+    #[error("SQL Server error: {0}")]
+    Mssql(String),
+
     #[error("Unsupported database type: {0}")]
     UnsupportedDatabaseType(String),
 
@@ -40,31 +53,61 @@ pub enum AppError {
     #[error("Not found: {0}")]
     NotFound(String),
 
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
     #[error("Not implemented: {0}")]
     NotImplemented(String),
 
     #[error("Bad request: {0}")]
     BadRequest(String),
 
-    #[error("SQL parsing error: {0}")]
-    SqlParsingError(String),
+    #[error("SQL parsing error: {message}")]
+    SqlParsingError {
+        message: String,
+        line: Option<usize>,   // 1-based line of the offending token
+        column: Option<usize>, // 1-based column of the offending token
+        near: Option<String>,  // Query text starting at the offending token
+    },
 
     #[error("Invalid query result: {0}")]
     InvalidQueryResult(String),
 
     #[error("AI error: {0}")]
     AiError(String),
+
+    #[error("Timeout: {0}")]
+    Timeout(String),
+
+    #[error("Cancelled: {0}")]
+    Cancelled(String),
+
+    /// The request clashes with existing state, e.g. a name already taken
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    /// The server is too busy to take the request right now
+    #[error("Overloaded: {0}")]
+    Overloaded(String),
+
+    /// Failure to persist server-side state, e.g. saved queries
+    #[error("Storage error: {0}")]
+    Storage(String),
+
+    #[error("Statement {index} failed: {source}")]
+    BatchStatement { index: usize, source: Box<AppError> },
 }
 
-impl IntoResponse for AuthError {
-    fn into_response(self) -> Response {
-        let (status, error_message) = match self {
+impl AuthError {
+    fn status_and_message(self) -> (StatusCode, String) {
+        match self {
             AuthError::InvalidToken(msg) => {
                 (StatusCode::UNAUTHORIZED, format!("Invalid Token: {}", msg))
             }
             AuthError::MissingCredentials => {
                 (StatusCode::UNAUTHORIZED, "Missing Credentials".to_string())
             }
+            AuthError::Forbidden => (StatusCode::FORBIDDEN, "Forbidden".to_string()),
             AuthError::TokenCreation => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "Internal error".to_string(),
@@ -73,20 +116,48 @@ impl IntoResponse for AuthError {
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "Internal error".to_string(),
             ),
-        };
+        }
+    }
+}
 
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        let (status, error_message) = self.status_and_message();
         let body = Json(json!({ "error": error_message }));
         (status, body).into_response()
     }
 }
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            AppError::Auth(auth_error) => {
-                // Reuse AuthError's IntoResponse implementation detail logic
-                return auth_error.into_response();
-            }
+impl AppError {
+    /// Short, stable name of the error variant, used as a metrics label
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AppError::Auth(_) => "auth",
+            AppError::Database(_)
+            | AppError::DatabaseMessage(_)
+            | AppError::Clickhouse(_)
+            | AppError::Mssql(_) => "database",
+            AppError::UnsupportedDatabaseType(_) => "unsupported_database_type",
+            AppError::Config(_) => "config",
+            AppError::NotFound(_) => "not_found",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::NotImplemented(_) => "not_implemented",
+            AppError::BadRequest(_) => "bad_request",
+            AppError::SqlParsingError { .. } => "sql_parsing",
+            AppError::InvalidQueryResult(_) => "invalid_query_result",
+            AppError::AiError(_) => "ai",
+            AppError::Timeout(_) => "timeout",
+            AppError::Cancelled(_) => "cancelled",
+            AppError::Conflict(_) => "conflict",
+            AppError::Overloaded(_) => "overloaded",
+            AppError::Storage(_) => "storage",
+            AppError::BatchStatement { source, .. } => source.kind(),
+        }
+    }
+
+    fn status_and_message(self) -> (StatusCode, String) {
+        match self {
+            AppError::Auth(auth_error) => auth_error.status_and_message(),
             AppError::Database(db_err) => {
                 // Log the detailed DB error for internal review
                 tracing::error!("Database error: {}", db_err);
@@ -96,6 +167,24 @@ impl IntoResponse for AppError {
                     "Internal database error".to_string(),
                 )
             }
+            AppError::DatabaseMessage(msg) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", msg),
+            ),
+            AppError::Clickhouse(msg) => {
+                tracing::error!("ClickHouse error: {}", msg);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Internal database error".to_string(),
+                )
+            }
+            AppError::Mssql(msg) => {
+                tracing::error!("SQL Server error: {}", msg);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Internal database error".to_string(),
+                )
+            }
             AppError::UnsupportedDatabaseType(db_type) => (
                 StatusCode::BAD_REQUEST,
                 format!("Unsupported database type: {}", db_type),
@@ -108,9 +197,13 @@ impl IntoResponse for AppError {
                 )
             }
             AppError::NotFound(msg) => (StatusCode::NOT_FOUND, msg),
+            AppError::Forbidden(msg) => (StatusCode::FORBIDDEN, msg),
             AppError::NotImplemented(msg) => (StatusCode::NOT_IMPLEMENTED, msg),
             AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg),
-            AppError::SqlParsingError(msg) => (StatusCode::BAD_REQUEST, msg),
+            AppError::SqlParsingError { message, .. } => (
+                StatusCode::BAD_REQUEST,
+                format!("SQL parsing error: {}", message),
+            ),
             AppError::InvalidQueryResult(msg) => {
                 warn!("Invalid query result: {}", msg);
                 (
@@ -125,9 +218,73 @@ impl IntoResponse for AppError {
                     format!("AI generation failed: {}", msg),
                 )
             }
+            AppError::Timeout(msg) => (StatusCode::GATEWAY_TIMEOUT, msg),
+            AppError::Cancelled(msg) => (StatusCode::CONFLICT, msg),
+            AppError::Conflict(msg) => (StatusCode::CONFLICT, msg),
+            AppError::Overloaded(msg) => (StatusCode::SERVICE_UNAVAILABLE, msg),
+            AppError::Storage(msg) => {
+                tracing::error!("Storage error: {}", msg);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Internal storage error".to_string(),
+                )
+            }
+            AppError::BatchStatement { index, source } => {
+                let (status, msg) = source.status_and_message();
+                (status, format!("Statement {} failed: {}", index, msg))
+            }
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let (status, body) = match self {
+            // Batch failures also report which statement failed
+            AppError::BatchStatement { index, source } => {
+                let (status, error_message) = source.status_and_message();
+                (status, json!({ "error": error_message, "index": index }))
+            }
+            // Parsing failures carry the position so the editor can mark it
+            AppError::SqlParsingError {
+                message,
+                line,
+                column,
+                near,
+            } => (
+                StatusCode::BAD_REQUEST,
+                json!({
+                    "error": format!("SQL parsing error: {}", message),
+                    "line": line,
+                    "column": column,
+                    "near": near,
+                }),
+            ),
+            other => {
+                let (status, error_message) = other.status_and_message();
+                (status, json!({ "error": error_message }))
+            }
         };
+        (status, Json(body)).into_response()
+    }
+}
 
-        let body = Json(json!({ "error": error_message }));
-        (status, body).into_response()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_forbidden_response() {
+        let response = AppError::Forbidden("Access to database 'users' is forbidden".to_string())
+            .into_response();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body,
+            json!({ "error": "Access to database 'users' is forbidden" })
+        );
     }
 }
@@ -0,0 +1,166 @@
+use super::SCHEMA_CACHE_KEY;
+use crate::{
+    config::DatabaseConfig,
+    db::{DatabaseInfo, DbPool, PoolHandler},
+    error::AppError,
+    state::AppState,
+};
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use tracing::info;
+
+/// Connect a new database and make it available without a restart. The
+/// change is in memory only; it is lost when the server restarts.
+pub async fn add_database(
+    State(state): State<AppState>,
+    Json(db_config): Json<DatabaseConfig>,
+) -> Result<(StatusCode, Json<DatabaseInfo>), AppError> {
+    let db_name = db_config.name.clone();
+    db_config
+        .validate()
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    info!(
+        "Adding database '{}' (type: {})",
+        db_name, db_config.db_type
+    );
+    let pool = DbPool::try_new(&db_config).await?;
+    let info = DatabaseInfo {
+        name: db_name.clone(),
+        db_type: db_config.db_type.to_string(),
+        kind: db_config.db_type.clone(),
+        connected: true,
+    };
+    // Claim the name atomically, so concurrent adds can't both win
+    let added = state
+        .databases
+        .pin()
+        .try_insert(db_name.clone(), db_config.clone())
+        .is_ok();
+    if !added {
+        pool.close().await;
+        return Err(AppError::Conflict(format!(
+            "Database '{}' already exists",
+            db_name
+        )));
+    }
+    state.pools.pin().insert(db_name, pool);
+    state.add_query_slots(&db_config);
+    invalidate_schemas(&state).await;
+
+    Ok((StatusCode::CREATED, Json(info)))
+}
+
+/// Remove a database and close its connection pool
+pub async fn remove_database(
+    State(state): State<AppState>,
+    Path(db_name): Path<String>,
+) -> Result<StatusCode, AppError> {
+    if state.databases.pin().remove(&db_name).is_none() {
+        return Err(AppError::NotFound(format!(
+            "Database '{}' not found",
+            db_name
+        )));
+    }
+
+    info!("Removing database '{}'", db_name);
+    let pools = state.pools.pin_owned();
+    if let Some(pool) = pools.remove(&db_name) {
+        pool.close().await;
+    }
+    state.query_slots.pin().remove(&db_name);
+    invalidate_schemas(&state).await;
+    // A database added later under the same name must not see these results
+    for (key, _) in state.query_cache.iter() {
+        if key.0 == db_name {
+            state.query_cache.invalidate(&*key).await;
+        }
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn invalidate_schemas(state: &AppState) {
+    state.schema_cache.invalidate(SCHEMA_CACHE_KEY).await;
+    state.table_schema_cache.invalidate_all();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        AppConfig,
+        db::{DatabaseType, QueryResult},
+        handlers::{list_databases, tests::test_user},
+    };
+    use serde_json::Value;
+    use std::{sync::Arc, time::Duration};
+
+    #[tokio::test]
+    async fn test_add_and_remove_database() {
+        let mut config = AppConfig::load("./config").unwrap();
+        config.databases.clear();
+        let state = AppState::new_for_test(config);
+
+        let db_config = DatabaseConfig {
+            name: "scratch".to_string(),
+            db_type: DatabaseType::Sqlite,
+            conn_string: "sqlite::memory:".to_string(),
+            query_timeout_secs: None,
+            max_concurrent_queries: None,
+            read_only: false,
+            default_limit: None,
+            max_limit: None,
+            ssl_mode: None,
+            ssl_root_cert: None,
+            allowed_tables: None,
+            denied_tables: Vec::new(),
+            masked_columns: Vec::new(),
+            required_roles: Vec::new(),
+            exact_numeric: false,
+            statement_cache_capacity: None,
+        };
+        let (status, Json(info)) = add_database(State(state.clone()), Json(db_config.clone()))
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        assert!(info.connected);
+        assert!(!state.is_read_only("scratch"));
+
+        let Json(databases) = list_databases(State(state.clone()), test_user()).await;
+        assert_eq!(databases.len(), 1);
+        assert!(databases[0].connected);
+
+        let result = add_database(State(state.clone()), Json(db_config)).await;
+        assert!(matches!(result, Err(AppError::Conflict(_))));
+        // The existing database is untouched
+        assert!(state.pools.pin().get("scratch").is_some());
+
+        let key = (
+            "scratch".to_string(),
+            "SELECT 1".to_string(),
+            "[]".to_string(),
+            10,
+        );
+        let result = Arc::new(QueryResult {
+            data: Value::Null,
+            execution_time: Duration::ZERO,
+            plan: None,
+            warnings: Vec::new(),
+        });
+        state.query_cache.insert(key.clone(), result).await;
+
+        let status = remove_database(State(state.clone()), Path("scratch".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert!(state.pools.pin().get("scratch").is_none());
+        assert!(state.query_cache.get(&key).await.is_none());
+
+        let result = remove_database(State(state), Path("scratch".to_string())).await;
+        assert!(matches!(result, Err(AppError::NotFound(_))));
+    }
+}
@@ -0,0 +1,241 @@
+use super::{ExecuteQueryRequest, ExecuteQueryResponse, audited_execute_query};
+use crate::{
+    auth::AuthedUser, db::is_mutation, error::AppError, queries::RequestId, state::AppState,
+};
+use axum::{
+    Json,
+    extract::{Extension, State},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+/// One side of a diff
+#[derive(Deserialize, Debug)]
+pub struct DiffQuerySpec {
+    pub db_name: String,
+    pub query: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DiffQueriesRequest {
+    pub left: DiffQuerySpec,  // "Before"
+    pub right: DiffQuerySpec, // "After"
+    pub key: String,          // Column identifying a row on both sides
+}
+
+/// Rows of the right result compared with the left one, matched by key
+#[derive(Serialize, Debug, PartialEq)]
+pub struct QueryDiff {
+    pub key: String,
+    pub counts: DiffCounts,
+    pub added: Vec<Value>,   // Only in the right result
+    pub removed: Vec<Value>, // Only in the left result
+    pub changed: Vec<ChangedRow>,
+    // Either result hit the max limit, so rows past it were not compared
+    pub truncated: bool,
+}
+
+#[derive(Serialize, Debug, PartialEq, Default)]
+pub struct DiffCounts {
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize,
+    pub unchanged: usize,
+}
+
+/// A key present on both sides with different rows
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ChangedRow {
+    pub key: Value,
+    pub before: Value,
+    pub after: Value,
+}
+
+/// Run two SELECTs the way `execute_query` runs them, each capped at its
+/// database's max limit, and diff their rows by the `key` column
+pub async fn diff_queries(
+    State(state): State<AppState>,
+    user: AuthedUser,
+    request_id: Option<Extension<RequestId>>,
+    Json(payload): Json<DiffQueriesRequest>,
+) -> Result<Json<QueryDiff>, AppError> {
+    if is_mutation(&payload.left.query) || is_mutation(&payload.right.query) {
+        return Err(AppError::BadRequest(
+            "Only SELECT queries can be diffed".to_string(),
+        ));
+    }
+    let (left, left_truncated) = run_rows(&state, &user, request_id.clone(), payload.left).await?;
+    let (right, right_truncated) = run_rows(&state, &user, request_id, payload.right).await?;
+
+    let mut diff = diff_rows(left, right, &payload.key)?;
+    diff.truncated = left_truncated || right_truncated;
+    Ok(Json(diff))
+}
+
+/// Rows of a query, and whether they were cut short by the limit
+async fn run_rows(
+    state: &AppState,
+    user: &AuthedUser,
+    request_id: Option<Extension<RequestId>>,
+    spec: DiffQuerySpec,
+) -> Result<(Vec<Value>, bool), AppError> {
+    let request = ExecuteQueryRequest {
+        db_name: spec.db_name,
+        query: spec.query,
+        // Clamped to the max limit of the database
+        limit: Some(usize::MAX),
+        ..Default::default()
+    };
+    let request_id = request_id.map(|Extension(RequestId(id))| id);
+    let (_, response) =
+        audited_execute_query(state, &user.0, request_id, request, "diff_queries").await?;
+    let ExecuteQueryResponse::Json(result) = response else {
+        unreachable!("a JSON result was requested");
+    };
+    match result.result {
+        Value::Array(rows) => Ok((rows, result.truncated)),
+        _ => Err(AppError::BadRequest(
+            "Only queries returning rows can be diffed".to_string(),
+        )),
+    }
+}
+
+/// Compare two row sets by a key column. Added rows keep the right order;
+/// removed and changed ones keep the left order.
+fn diff_rows(left: Vec<Value>, right: Vec<Value>, key: &str) -> Result<QueryDiff, AppError> {
+    let mut right_by_key = HashMap::with_capacity(right.len());
+    let mut right_keys = Vec::with_capacity(right.len());
+    for row in right {
+        let row_key = key_of(&row, key)?;
+        right_keys.push(row_key.clone());
+        if right_by_key.insert(row_key, row).is_some() {
+            return Err(duplicate_key(key));
+        }
+    }
+
+    let mut diff = QueryDiff {
+        key: key.to_string(),
+        counts: DiffCounts::default(),
+        added: Vec::new(),
+        removed: Vec::new(),
+        changed: Vec::new(),
+        truncated: false,
+    };
+    let mut left_keys = HashSet::with_capacity(left.len());
+    for row in left {
+        let row_key = key_of(&row, key)?;
+        if !left_keys.insert(row_key.clone()) {
+            return Err(duplicate_key(key));
+        }
+        match right_by_key.remove(&row_key) {
+            None => diff.removed.push(row),
+            Some(after) if after == row => diff.counts.unchanged += 1,
+            Some(after) => diff.changed.push(ChangedRow {
+                key: row[key].clone(),
+                before: row,
+                after,
+            }),
+        }
+    }
+    // Whatever is left on the right side has no match on the left
+    diff.added = right_keys
+        .iter()
+        .filter_map(|row_key| right_by_key.remove(row_key))
+        .collect();
+
+    diff.counts.added = diff.added.len();
+    diff.counts.removed = diff.removed.len();
+    diff.counts.changed = diff.changed.len();
+    Ok(diff)
+}
+
+/// The key column of a row, as JSON text so any value type can be hashed
+fn key_of(row: &Value, key: &str) -> Result<String, AppError> {
+    row.get(key)
+        .map(Value::to_string)
+        .ok_or_else(|| AppError::BadRequest(format!("Result has no key column '{}'", key)))
+}
+
+fn duplicate_key(key: &str) -> AppError {
+    AppError::BadRequest(format!(
+        "Key column '{}' is not unique within a result",
+        key
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AppConfig, handlers::tests::test_user};
+    use serde_json::json;
+
+    #[test]
+    fn test_diff_rows() {
+        let left = vec![
+            json!({ "id": 1, "name": "a" }),
+            json!({ "id": 2, "name": "b" }),
+            json!({ "id": 3, "name": "c" }),
+        ];
+        let right = vec![
+            json!({ "id": 4, "name": "d" }),
+            json!({ "id": 3, "name": "c" }),
+            json!({ "id": 2, "name": "B" }),
+        ];
+        let diff = diff_rows(left, right, "id").unwrap();
+        assert_eq!(diff.added, vec![json!({ "id": 4, "name": "d" })]);
+        assert_eq!(diff.removed, vec![json!({ "id": 1, "name": "a" })]);
+        assert_eq!(
+            diff.changed,
+            vec![ChangedRow {
+                key: json!(2),
+                before: json!({ "id": 2, "name": "b" }),
+                after: json!({ "id": 2, "name": "B" }),
+            }]
+        );
+        assert_eq!(diff.counts.unchanged, 1);
+
+        let duplicated = vec![json!({ "id": 1 }), json!({ "id": 1 })];
+        let result = diff_rows(duplicated, Vec::new(), "id");
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+        let result = diff_rows(vec![json!({ "id": 1 })], Vec::new(), "missing");
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn test_diff_queries() {
+        let state = AppState::new(AppConfig::load("./config").unwrap())
+            .await
+            .unwrap();
+        let spec = |query: &str| DiffQuerySpec {
+            db_name: "users".to_string(),
+            query: query.to_string(),
+        };
+        let Json(diff) = diff_queries(
+            State(state),
+            test_user(),
+            None,
+            Json(DiffQueriesRequest {
+                left: spec("SELECT id, name FROM users WHERE id <= 3"),
+                right: spec(
+                    "SELECT id, CASE WHEN id = 2 THEN 'Robert' ELSE name END AS name \
+                     FROM users WHERE id BETWEEN 2 AND 4",
+                ),
+                key: "id".to_string(),
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            diff.counts,
+            DiffCounts {
+                added: 1,
+                removed: 1,
+                changed: 1,
+                unchanged: 1,
+            }
+        );
+        assert_eq!(diff.changed[0].after["name"], "Robert");
+        assert!(!diff.truncated);
+    }
+}
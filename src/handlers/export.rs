@@ -0,0 +1,223 @@
+use crate::error::AppError;
+use serde_json::Value;
+#[cfg(feature = "arrow")]
+use {
+    arrow::{
+        datatypes::Schema,
+        error::ArrowError,
+        ipc::writer::StreamWriter,
+        json::{ReaderBuilder, reader::infer_json_schema_from_iterator},
+        record_batch::RecordBatch,
+    },
+    parquet::arrow::ArrowWriter,
+    std::sync::Arc,
+};
+
+/// Flatten a JSON array of row objects into CSV text. The header row is the
+/// union of all object keys, in the order they are first seen.
+pub fn json_to_csv(data: &Value) -> Result<String, AppError> {
+    let rows = tabular_rows(data, "CSV")?;
+    let columns = columns(rows);
+
+    let mut csv = String::new();
+    push_record(&mut csv, columns.iter().map(|c| c.to_string()));
+    for row in rows {
+        push_record(
+            &mut csv,
+            columns.iter().map(|c| match row.get(c) {
+                None | Some(Value::Null) => String::new(),
+                Some(Value::String(s)) => s.clone(),
+                Some(v) => v.to_string(), // numbers, booleans and nested JSON
+            }),
+        );
+    }
+    Ok(csv)
+}
+
+/// Serialize a JSON array of row objects as an Arrow IPC stream
+#[cfg(feature = "arrow")]
+pub fn json_to_arrow(data: &Value) -> Result<Vec<u8>, AppError> {
+    let (schema, batches) = record_batches(data, "Arrow")?;
+    let mut writer = StreamWriter::try_new(Vec::new(), &schema).map_err(arrow_error)?;
+    for batch in &batches {
+        writer.write(batch).map_err(arrow_error)?;
+    }
+    writer.into_inner().map_err(arrow_error)
+}
+
+/// Serialize a JSON array of row objects as a Parquet file
+#[cfg(feature = "arrow")]
+pub fn json_to_parquet(data: &Value) -> Result<Vec<u8>, AppError> {
+    let (schema, batches) = record_batches(data, "Parquet")?;
+    let mut writer = ArrowWriter::try_new(Vec::new(), schema, None)
+        .map_err(|e| AppError::InvalidQueryResult(e.to_string()))?;
+    for batch in &batches {
+        writer
+            .write(batch)
+            .map_err(|e| AppError::InvalidQueryResult(e.to_string()))?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| AppError::InvalidQueryResult(e.to_string()))
+}
+
+/// Convert rows into Arrow record batches. Column types are inferred from
+/// the values: integers mixed with floats become Float64, nested objects
+/// structs, arrays lists, and columns mixing other types strings.
+#[cfg(feature = "arrow")]
+fn record_batches(data: &Value, format: &str) -> Result<(Arc<Schema>, Vec<RecordBatch>), AppError> {
+    let rows = tabular_rows(data, format)?;
+    let inferred = infer_json_schema_from_iterator(rows.iter().map(Ok::<_, ArrowError>))
+        .map_err(arrow_error)?;
+    // Inference loses the column order, so restore it
+    let fields: Vec<_> = columns(rows)
+        .into_iter()
+        .filter_map(|column| inferred.field_with_name(column).ok().cloned())
+        .collect();
+    let schema = Arc::new(Schema::new(fields));
+
+    let ndjson = rows
+        .iter()
+        .map(Value::to_string)
+        .collect::<Vec<_>>()
+        .join("\n");
+    let reader = ReaderBuilder::new(schema.clone())
+        .with_coerce_primitive(true)
+        .build(ndjson.as_bytes())
+        .map_err(arrow_error)?;
+    let batches = reader.collect::<Result<_, _>>().map_err(arrow_error)?;
+    Ok((schema, batches))
+}
+
+#[cfg(feature = "arrow")]
+fn arrow_error(e: ArrowError) -> AppError {
+    AppError::InvalidQueryResult(e.to_string())
+}
+
+/// The rows of a tabular result: an array of objects, or nothing at all
+fn tabular_rows<'a>(data: &'a Value, format: &str) -> Result<&'a [Value], AppError> {
+    let rows = match data {
+        Value::Array(rows) => rows.as_slice(),
+        Value::Null => &[],
+        _ => {
+            return Err(AppError::BadRequest(format!(
+                "{} export requires a tabular (row array) result",
+                format
+            )));
+        }
+    };
+    if !rows.iter().all(Value::is_object) {
+        return Err(AppError::BadRequest(format!(
+            "{} export requires each row to be an object",
+            format
+        )));
+    }
+    Ok(rows)
+}
+
+/// The union of the keys of all rows, in the order they are first seen
+fn columns(rows: &[Value]) -> Vec<&str> {
+    let mut columns: Vec<&str> = Vec::new();
+    for key in rows
+        .iter()
+        .filter_map(Value::as_object)
+        .flat_map(|row| row.keys())
+    {
+        if !columns.contains(&key.as_str()) {
+            columns.push(key);
+        }
+    }
+    columns
+}
+
+fn push_record(csv: &mut String, fields: impl Iterator<Item = String>) {
+    for (i, field) in fields.enumerate() {
+        if i > 0 {
+            csv.push(',');
+        }
+        if field.contains([',', '"', '\n', '\r']) {
+            csv.push('"');
+            csv.push_str(&field.replace('"', "\"\""));
+            csv.push('"');
+        } else {
+            csv.push_str(&field);
+        }
+    }
+    csv.push_str("\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_json_to_csv() {
+        let data = json!([
+            { "id": 1, "name": "Alice, Jr.", "tags": ["a"] },
+            { "id": 2, "name": "Bob \"B\"", "email": null },
+            { "id": 3, "name": "multi\nline", "email": "c@example.com" },
+        ]);
+        let csv = json_to_csv(&data).unwrap();
+        assert_eq!(
+            csv,
+            "id,name,tags,email\r\n\
+             1,\"Alice, Jr.\",\"[\"\"a\"\"]\",\r\n\
+             2,\"Bob \"\"B\"\"\",,\r\n\
+             3,\"multi\nline\",,c@example.com\r\n"
+        );
+    }
+
+    #[test]
+    fn test_json_to_csv_rejects_non_tabular() {
+        assert!(matches!(
+            json_to_csv(&json!({ "hits": [] })),
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(matches!(
+            json_to_csv(&json!([1, 2, 3])),
+            Err(AppError::BadRequest(_))
+        ));
+        assert_eq!(json_to_csv(&Value::Null).unwrap(), "\r\n");
+    }
+
+    #[cfg(feature = "arrow")]
+    #[test]
+    fn test_json_to_arrow() {
+        use arrow::{datatypes::DataType, ipc::reader::StreamReader};
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let data = json!([
+            { "name": "a", "id": 1, "score": 1, "tags": ["x"] },
+            { "name": "b", "id": 2, "score": 2.5, "tags": null, "address": { "city": "c" } },
+            { "name": null, "id": 3, "score": "n/a" },
+        ]);
+        let bytes = json_to_arrow(&data).unwrap();
+        let reader = StreamReader::try_new(bytes.as_slice(), None).unwrap();
+        let schema = reader.schema();
+        let data_type = |name: &str| schema.field_with_name(name).unwrap().data_type().clone();
+        assert_eq!(data_type("name"), DataType::Utf8);
+        assert_eq!(data_type("id"), DataType::Int64);
+        // Numbers mixed with a string fall back to strings
+        assert_eq!(data_type("score"), DataType::Utf8);
+        assert!(matches!(data_type("tags"), DataType::List(_)));
+        assert!(matches!(data_type("address"), DataType::Struct(_)));
+        let rows: usize = reader.map(|batch| batch.unwrap().num_rows()).sum();
+        assert_eq!(rows, 3);
+
+        let bytes = json_to_parquet(&data).unwrap();
+        let reader = ParquetRecordBatchReaderBuilder::try_new(axum::body::Bytes::from(bytes))
+            .unwrap()
+            .build()
+            .unwrap();
+        let rows: usize = reader.map(|batch| batch.unwrap().num_rows()).sum();
+        assert_eq!(rows, 3);
+
+        assert!(json_to_arrow(&Value::Null).is_ok());
+        assert!(json_to_parquet(&json!([])).is_ok());
+        assert!(matches!(
+            json_to_parquet(&json!([1])),
+            Err(AppError::BadRequest(_))
+        ));
+    }
+}
@@ -0,0 +1,202 @@
+use super::{CacheStatus, ExecuteQueryRequest, ExecuteQueryResponse, audited_execute_query};
+use crate::{
+    auth::AuthedUser, error::AppError, queries::RequestId, saved_queries::SavedQuery,
+    state::AppState,
+};
+use axum::{
+    Json,
+    extract::{Extension, Path, State},
+    http::StatusCode,
+};
+use serde::Deserialize;
+use serde_json::Value;
+
+#[derive(Deserialize, Debug)]
+pub struct SaveQueryRequest {
+    pub name: String,
+    pub db_name: String,
+    pub query: String,
+}
+
+/// Options for running a saved query; all optional, as for execute-query
+#[derive(Deserialize, Debug, Default)]
+pub struct RunSavedQueryRequest {
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+    pub format: Option<String>,
+    #[serde(default)]
+    pub params: Vec<Value>,
+    #[serde(default)]
+    pub envelope: bool,
+}
+
+/// Save a query for the caller
+pub async fn save_query(
+    State(state): State<AppState>,
+    AuthedUser(claims): AuthedUser,
+    Json(payload): Json<SaveQueryRequest>,
+) -> Result<(StatusCode, Json<SavedQuery>), AppError> {
+    if payload.name.trim().is_empty() {
+        return Err(AppError::BadRequest(
+            "Saved query name must not be empty".to_string(),
+        ));
+    }
+    state.authorize(&claims, &payload.db_name)?;
+    if state.database_config(&payload.db_name).is_none() {
+        return Err(AppError::NotFound(format!(
+            "Database '{}' not found",
+            payload.db_name
+        )));
+    }
+    let saved = state
+        .saved_queries
+        .insert(&claims.sub, payload.name, payload.db_name, payload.query)
+        .await?;
+    Ok((StatusCode::CREATED, Json(saved)))
+}
+
+/// The caller's saved queries
+pub async fn list_saved_queries(
+    State(state): State<AppState>,
+    AuthedUser(claims): AuthedUser,
+) -> Json<Vec<SavedQuery>> {
+    Json(state.saved_queries.list(&claims.sub).await)
+}
+
+pub async fn get_saved_query(
+    State(state): State<AppState>,
+    AuthedUser(claims): AuthedUser,
+    Path(id): Path<String>,
+) -> Result<Json<SavedQuery>, AppError> {
+    find(&state, &claims.sub, &id).await.map(Json)
+}
+
+pub async fn delete_saved_query(
+    State(state): State<AppState>,
+    AuthedUser(claims): AuthedUser,
+    Path(id): Path<String>,
+) -> Result<StatusCode, AppError> {
+    if state.saved_queries.remove(&claims.sub, &id).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(not_found(&id))
+    }
+}
+
+/// Run a saved query the way `execute_query` runs one, so it is authorized,
+/// limited, masked and audited like any other query. The body is optional.
+pub async fn run_saved_query(
+    State(state): State<AppState>,
+    user: AuthedUser,
+    request_id: Option<Extension<RequestId>>,
+    Path(id): Path<String>,
+    options: Option<Json<RunSavedQueryRequest>>,
+) -> Result<(CacheStatus, ExecuteQueryResponse), AppError> {
+    let saved = find(&state, &user.0.sub, &id).await?;
+    let Json(options) = options.unwrap_or_default();
+    let request = ExecuteQueryRequest {
+        db_name: saved.db_name,
+        query: saved.query,
+        limit: options.limit,
+        offset: options.offset,
+        format: options.format,
+        params: options.params,
+        envelope: options.envelope,
+        ..Default::default()
+    };
+    let request_id = request_id.map(|Extension(RequestId(id))| id);
+    audited_execute_query(&state, &user.0, request_id, request, "run_saved_query").await
+}
+
+async fn find(state: &AppState, sub: &str, id: &str) -> Result<SavedQuery, AppError> {
+    state
+        .saved_queries
+        .get(sub, id)
+        .await
+        .ok_or_else(|| not_found(id))
+}
+
+// Other users' queries are reported as missing, not forbidden
+fn not_found(id: &str) -> AppError {
+    AppError::NotFound(format!("Saved query '{}' not found", id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AppConfig, Claims};
+
+    fn user(sub: &str) -> AuthedUser {
+        AuthedUser(Claims {
+            sub: sub.to_string(),
+            exp: usize::MAX,
+            roles: Vec::new(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_saved_queries() {
+        let state = AppState::new(AppConfig::load("./config").unwrap())
+            .await
+            .unwrap();
+        let (status, Json(saved)) = save_query(
+            State(state.clone()),
+            user("alice"),
+            Json(SaveQueryRequest {
+                name: "First users".to_string(),
+                db_name: "users".to_string(),
+                query: "SELECT id FROM users ORDER BY id".to_string(),
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(saved.sub, "alice");
+
+        let Json(listed) = list_saved_queries(State(state.clone()), user("alice")).await;
+        assert_eq!(listed, vec![saved.clone()]);
+        let Json(listed) = list_saved_queries(State(state.clone()), user("bob")).await;
+        assert!(listed.is_empty());
+
+        let (_, ExecuteQueryResponse::Json(result)) = run_saved_query(
+            State(state.clone()),
+            user("alice"),
+            None,
+            Path(saved.id.clone()),
+            Some(Json(RunSavedQueryRequest {
+                limit: Some(2),
+                ..Default::default()
+            })),
+        )
+        .await
+        .unwrap() else {
+            panic!("Expected a JSON response");
+        };
+        assert_eq!(
+            serde_json::to_value(result).unwrap()["result"],
+            serde_json::json!([{ "id": 1 }, { "id": 2 }])
+        );
+
+        // Another user can neither run nor delete it
+        let result = run_saved_query(
+            State(state.clone()),
+            user("bob"),
+            None,
+            Path(saved.id.clone()),
+            None,
+        )
+        .await;
+        assert!(matches!(result, Err(AppError::NotFound(_))));
+        let result =
+            delete_saved_query(State(state.clone()), user("bob"), Path(saved.id.clone())).await;
+        assert!(matches!(result, Err(AppError::NotFound(_))));
+
+        let status =
+            delete_saved_query(State(state.clone()), user("alice"), Path(saved.id.clone()))
+                .await
+                .unwrap();
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let result = get_saved_query(State(state), user("alice"), Path(saved.id)).await;
+        assert!(matches!(result, Err(AppError::NotFound(_))));
+    }
+}
@@ -0,0 +1,240 @@
+use super::{ExecuteQueryRequest, StreamedQuery, open_row_stream, streamed_query_text};
+use crate::{Claims, auth::AuthedUser, db::PoolHandler, error::AppError, state::AppState};
+use axum::{
+    extract::{
+        State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    response::Response,
+};
+use futures::StreamExt;
+use serde::Serialize;
+use serde_json::Value;
+use std::time::Instant;
+use tracing::{info, warn};
+
+/// Rows sent per `row_batch` frame
+const ROW_BATCH_SIZE: usize = 100;
+
+/// Progress frames sent to the client while a query runs
+#[derive(Serialize, Debug)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum QueryProgress {
+    Started,
+    Planning,
+    Fetching,
+    RowBatch {
+        rows: Vec<Value>,
+    },
+    Done {
+        row_count: usize,
+        #[serde(rename = "executionTime")]
+        execution_time: f64,
+    },
+    Error {
+        error: String,
+    },
+}
+
+/// Run queries over a WebSocket. Each text message is an execute-query
+/// request; rows are streamed back in batches, and the query is cancelled
+/// if the client disconnects.
+pub async fn ws_query(
+    State(state): State<AppState>,
+    AuthedUser(claims): AuthedUser,
+    ws: WebSocketUpgrade,
+) -> Response {
+    ws.on_upgrade(move |socket| handle_socket(state, claims, socket))
+}
+
+async fn handle_socket(state: AppState, claims: Claims, mut socket: WebSocket) {
+    while let Some(Ok(message)) = socket.recv().await {
+        let request = match message {
+            Message::Text(text) => serde_json::from_str::<ExecuteQueryRequest>(&text),
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        let connected = match request {
+            Ok(request) => match stream_query(&state, &claims, &mut socket, request).await {
+                Ok(connected) => connected,
+                Err(e) => {
+                    send(
+                        &mut socket,
+                        QueryProgress::Error {
+                            error: e.to_string(),
+                        },
+                    )
+                    .await
+                }
+            },
+            Err(e) => {
+                let error = format!("Invalid query request: {}", e);
+                send(&mut socket, QueryProgress::Error { error }).await
+            }
+        };
+        if !connected {
+            break;
+        }
+    }
+}
+
+/// Run one query, sending progress frames. Returns whether the client is
+/// still connected.
+async fn stream_query(
+    state: &AppState,
+    claims: &Claims,
+    socket: &mut WebSocket,
+    request: ExecuteQueryRequest,
+) -> Result<bool, AppError> {
+    state.authorize(claims, &request.db_name)?;
+    let query = streamed_query_text(&request)?;
+    if !send(socket, QueryProgress::Started).await {
+        return Ok(false);
+    }
+    let db_name = request.db_name;
+    let pools = state.pools.pin_owned();
+    let pool = pools
+        .get(&db_name)
+        .ok_or_else(|| AppError::NotFound(format!("Database '{}' not found", db_name)))?;
+
+    if !send(socket, QueryProgress::Planning).await {
+        return Ok(false);
+    }
+    let start = Instant::now();
+    let streamed = StreamedQuery {
+        sub: &claims.sub,
+        action: "ws_query",
+        db_name: &db_name,
+        query: &query,
+        limit: request.limit,
+        offset: request.offset,
+    };
+    let mut rows = open_row_stream(state, pool, streamed, start).await?;
+    let mask = pool.access().column_mask(&query);
+    if !send(socket, QueryProgress::Fetching).await {
+        return Ok(false);
+    }
+
+    let mut batch = Vec::with_capacity(ROW_BATCH_SIZE);
+    let mut row_count = 0;
+    loop {
+        tokio::select! {
+            row = rows.next() => match row {
+                Some(row) => {
+                    let mut row = row?;
+                    mask.apply(&mut row);
+                    batch.push(row);
+                    row_count += 1;
+                    if batch.len() == ROW_BATCH_SIZE {
+                        let rows = std::mem::replace(&mut batch, Vec::with_capacity(ROW_BATCH_SIZE));
+                        if !send(socket, QueryProgress::RowBatch { rows }).await {
+                            return Ok(false);
+                        }
+                    }
+                }
+                None => break,
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => {
+                    // Dropping the row stream cancels the query
+                    info!(database = %db_name, "Client disconnected, cancelling query");
+                    return Ok(false);
+                }
+                Some(Ok(_)) => warn!("Ignoring message received while a query is running"),
+            },
+        }
+    }
+
+    if !batch.is_empty() && !send(socket, QueryProgress::RowBatch { rows: batch }).await {
+        return Ok(false);
+    }
+    let done = QueryProgress::Done {
+        row_count,
+        execution_time: start.elapsed().as_secs_f64(),
+    };
+    Ok(send(socket, done).await)
+}
+
+/// Send a frame; returns false if the client has gone away
+async fn send(socket: &mut WebSocket, progress: QueryProgress) -> bool {
+    let text = match serde_json::to_string(&progress) {
+        Ok(text) => text,
+        Err(e) => {
+            warn!("Failed to serialize query progress: {}", e);
+            return true;
+        }
+    };
+    socket.send(Message::Text(text.into())).await.is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AppConfig;
+    use futures::SinkExt;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::{connect_async, tungstenite};
+
+    #[tokio::test]
+    async fn test_ws_query() {
+        let state = AppState::new(AppConfig::load("./config").unwrap())
+            .await
+            .unwrap();
+        // Stands in for the auth middleware
+        let claims = Claims {
+            sub: "test_user".to_string(),
+            exp: usize::MAX,
+            roles: Vec::new(),
+        };
+        let app = axum::Router::new()
+            .route("/ws", axum::routing::get(ws_query))
+            .layer(axum::Extension(claims))
+            .with_state(state);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let (mut client, _) = connect_async(format!("ws://{}/ws", addr)).await.unwrap();
+        let request =
+            r#"{"db_name": "users", "query": "SELECT id FROM users ORDER BY id", "limit": 3}"#;
+        client
+            .send(tungstenite::Message::Text(request.into()))
+            .await
+            .unwrap();
+
+        let mut statuses = Vec::new();
+        let mut rows = 0;
+        while let Some(Ok(tungstenite::Message::Text(text))) = client.next().await {
+            let frame: Value = serde_json::from_str(&text).unwrap();
+            let status = frame["status"].as_str().unwrap().to_string();
+            if status == "row_batch" {
+                rows += frame["rows"].as_array().unwrap().len();
+            }
+            let done = status == "done" || status == "error";
+            statuses.push(status);
+            if done {
+                assert_eq!(frame["row_count"], 3);
+                break;
+            }
+        }
+        assert_eq!(
+            statuses,
+            ["started", "planning", "fetching", "row_batch", "done"]
+        );
+        assert_eq!(rows, 3);
+
+        // Streams can't bind parameters, so they are refused, not dropped
+        let request =
+            r#"{"db_name": "users", "query": "SELECT id FROM users WHERE id = $1", "params": [1]}"#;
+        client
+            .send(tungstenite::Message::Text(request.into()))
+            .await
+            .unwrap();
+        let Some(Ok(tungstenite::Message::Text(text))) = client.next().await else {
+            panic!("Expected a frame");
+        };
+        let frame: Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(frame["status"], "error");
+        assert!(frame["error"].as_str().unwrap().contains("Bind parameters"));
+    }
+}
@@ -1,8 +1,9 @@
 use clap::Parser;
-use r2_data2::{AppConfig, AppState, get_router};
-use std::net::SocketAddr;
-use tokio::net::TcpListener;
-use tracing::info;
+use r2_data2::{AppConfig, AppState, get_router, pg_notice_layer};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+use tokio::{net::TcpListener, signal, sync::Notify};
+use tracing::{info, warn};
+use tracing_subscriber::{EnvFilter, fmt, prelude::*};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -14,21 +15,71 @@ struct Args {
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt::init();
+    // Postgres notices are captured for query results regardless of the log level
+    tracing_subscriber::registry()
+        .with(fmt::layer().with_filter(EnvFilter::from_default_env()))
+        .with(pg_notice_layer())
+        .init();
 
     let args = Args::parse();
 
     let config = AppConfig::load(&args.config_path)?;
     info!("Loaded configuration: {:?}", config);
     let addr: SocketAddr = config.server_addr.parse()?;
+    let grace = Duration::from_secs(config.shutdown_grace_secs);
 
     let state = AppState::new(config).await?;
 
-    let app = get_router(state);
+    let app = get_router(state.clone())?;
 
     info!("listening on {}", addr);
     let listener = TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+
+    // Stop accepting connections on a signal, then give in-flight requests
+    // up to `grace` to finish
+    let shutting_down = Arc::new(Notify::new());
+    let server = axum::serve(listener, app).with_graceful_shutdown({
+        let shutting_down = shutting_down.clone();
+        async move {
+            shutdown_signal().await;
+            shutting_down.notify_one();
+        }
+    });
+    tokio::select! {
+        result = server => result?,
+        _ = async {
+            shutting_down.notified().await;
+            tokio::time::sleep(grace).await;
+        } => warn!("In-flight requests did not finish within {:?}, forcing shutdown", grace),
+    }
+
+    state.close_pools().await;
+    info!("Shutdown complete");
 
     Ok(())
 }
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        signal::ctrl_c()
+            .await
+            .expect("failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    info!("Shutdown signal received, draining in-flight requests");
+}
@@ -0,0 +1,134 @@
+use crate::error::AppError;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry, TextEncoder,
+};
+use std::time::Duration;
+
+/// Prometheus metrics for executed queries
+pub struct Metrics {
+    registry: Registry,
+    queries_total: IntCounterVec,
+    query_errors_total: IntCounterVec,
+    query_duration_seconds: HistogramVec,
+    slow_queries_total: IntCounterVec,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        let queries_total = IntCounterVec::new(
+            Opts::new("r2_queries_total", "Queries executed, by database"),
+            &["db_name", "backend"],
+        )
+        .expect("valid metric definition");
+        let query_errors_total = IntCounterVec::new(
+            Opts::new("r2_query_errors_total", "Failed queries, by error kind"),
+            &["db_name", "backend", "kind"],
+        )
+        .expect("valid metric definition");
+        let query_duration_seconds = HistogramVec::new(
+            HistogramOpts::new("r2_query_duration_seconds", "Query execution time"),
+            &["backend"],
+        )
+        .expect("valid metric definition");
+        let slow_queries_total = IntCounterVec::new(
+            Opts::new(
+                "r2_slow_queries_total",
+                "Queries slower than the slow query threshold, by database",
+            ),
+            &["db_name", "backend"],
+        )
+        .expect("valid metric definition");
+
+        registry
+            .register(Box::new(queries_total.clone()))
+            .expect("metric registered once");
+        registry
+            .register(Box::new(query_errors_total.clone()))
+            .expect("metric registered once");
+        registry
+            .register(Box::new(query_duration_seconds.clone()))
+            .expect("metric registered once");
+        registry
+            .register(Box::new(slow_queries_total.clone()))
+            .expect("metric registered once");
+
+        Self {
+            registry,
+            queries_total,
+            query_errors_total,
+            query_duration_seconds,
+            slow_queries_total,
+        }
+    }
+
+    /// Record one query against `db_name`, and its error if it failed
+    pub fn observe_query(
+        &self,
+        db_name: &str,
+        backend: &str,
+        elapsed: Duration,
+        error: Option<&AppError>,
+    ) {
+        self.queries_total
+            .with_label_values(&[db_name, backend])
+            .inc();
+        self.query_duration_seconds
+            .with_label_values(&[backend])
+            .observe(elapsed.as_secs_f64());
+        if let Some(e) = error {
+            self.query_errors_total
+                .with_label_values(&[db_name, backend, e.kind()])
+                .inc();
+        }
+    }
+
+    /// Count a query that ran past the slow query threshold
+    pub fn observe_slow_query(&self, db_name: &str, backend: &str) {
+        self.slow_queries_total
+            .with_label_values(&[db_name, backend])
+            .inc();
+    }
+
+    /// Render all metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut buf = Vec::new();
+        // Encoding into a Vec only fails on invalid metric families
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buf) {
+            tracing::error!("Failed to encode metrics: {}", e);
+        }
+        String::from_utf8(buf).unwrap_or_default()
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_metrics() {
+        let metrics = Metrics::new();
+        metrics.observe_query("users", "postgres", Duration::from_millis(5), None);
+        metrics.observe_query(
+            "users",
+            "postgres",
+            Duration::from_millis(7),
+            Some(&AppError::Timeout("slow".to_string())),
+        );
+        metrics.observe_slow_query("users", "postgres");
+
+        let text = metrics.render();
+        assert!(text.contains(r#"r2_queries_total{backend="postgres",db_name="users"} 2"#));
+        assert!(text.contains(
+            r#"r2_query_errors_total{backend="postgres",db_name="users",kind="timeout"} 1"#
+        ));
+        assert!(text.contains(r#"r2_query_duration_seconds_count{backend="postgres"} 2"#));
+        assert!(text.contains(r#"r2_slow_queries_total{backend="postgres",db_name="users"} 1"#));
+    }
+}
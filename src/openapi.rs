@@ -0,0 +1,89 @@
+use crate::{
+    db::{
+        ColumnInfo, ColumnStats, ColumnType, DatabaseInfo, DatabaseType, TableInfo, TableSchema,
+        TableType,
+    },
+    handlers::{
+        self, ApiQueryResult, ExecuteQueryRequest, GenerateQueryRequest, GenerateQueryResponse,
+        QueryEnvelope, QueryMeta,
+    },
+    state::AppState,
+};
+use axum::Router;
+use serde::Serialize;
+use utoipa::{
+    Modify, OpenApi, ToSchema,
+    openapi::security::{Http, HttpAuthScheme, SecurityScheme},
+};
+use utoipa_swagger_ui::SwaggerUi;
+
+/// Body of every error response
+#[derive(Serialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+/// JSON body of execute-query: an envelope when the request sets `envelope`
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+#[allow(dead_code)] // Only describes the body; never built
+pub enum JsonQueryResult {
+    Result(ApiQueryResult),
+    Envelope(QueryEnvelope),
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "r2-data2"),
+    paths(
+        handlers::list_databases,
+        handlers::list_tables,
+        handlers::list_schemas,
+        handlers::get_table_schema,
+        handlers::table_exists,
+        handlers::execute_query,
+        handlers::gen_query,
+    ),
+    components(schemas(
+        ApiQueryResult,
+        ColumnInfo,
+        ColumnStats,
+        ColumnType,
+        DatabaseInfo,
+        DatabaseType,
+        ErrorResponse,
+        ExecuteQueryRequest,
+        GenerateQueryRequest,
+        GenerateQueryResponse,
+        JsonQueryResult,
+        QueryEnvelope,
+        QueryMeta,
+        TableInfo,
+        TableSchema,
+        TableType,
+    )),
+    modifiers(&BearerAuth),
+    security(("bearer_auth" = [])),
+)]
+struct ApiDoc;
+
+// Every documented route takes the JWT from /api/login as a bearer token
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer_auth",
+            SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+        );
+    }
+}
+
+/// Public routes serving the spec at `/api/openapi.json` and Swagger UI at
+/// `/api/docs`
+pub fn routes() -> Router<AppState> {
+    SwaggerUi::new("/api/docs")
+        .url("/api/openapi.json", ApiDoc::openapi())
+        .into()
+}
@@ -0,0 +1,141 @@
+use crate::{db::BACKEND_PID, error::AppError};
+use std::{
+    future::Future,
+    sync::{Arc, OnceLock},
+};
+use tokio::sync::Notify;
+
+/// Id of the current request, stored in the request extensions by the
+/// request-id middleware
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+/// A query that is currently running
+struct ActiveQuery {
+    sub: String, // Subject of the caller that started it
+    db_name: String,
+    cancelled: Arc<Notify>,
+    backend_pid: Arc<OnceLock<i32>>, // Set by backends that can cancel server-side
+}
+
+/// In-flight queries by request id, so they can be cancelled
+#[derive(Default)]
+pub struct ActiveQueries(papaya::HashMap<String, ActiveQuery>);
+
+impl ActiveQueries {
+    /// Run a query for `sub`, cancellable under `request_id` until it
+    /// completes. Without a request id the query simply runs; a request id
+    /// that is already in flight is rejected.
+    pub async fn run<T>(
+        &self,
+        request_id: Option<&str>,
+        sub: &str,
+        db_name: &str,
+        fut: impl Future<Output = Result<T, AppError>>,
+    ) -> Result<T, AppError> {
+        let Some(request_id) = request_id else {
+            return fut.await;
+        };
+        let cancelled = Arc::new(Notify::new());
+        let backend_pid = Arc::new(OnceLock::new());
+        let inserted = self
+            .0
+            .pin()
+            .try_insert(
+                request_id.to_string(),
+                ActiveQuery {
+                    sub: sub.to_string(),
+                    db_name: db_name.to_string(),
+                    cancelled: cancelled.clone(),
+                    backend_pid: backend_pid.clone(),
+                },
+            )
+            .is_ok();
+        if !inserted {
+            return Err(AppError::BadRequest(format!(
+                "A query with request id '{}' is already running",
+                request_id
+            )));
+        }
+        let _deregister = Deregister {
+            queries: self,
+            request_id,
+        };
+
+        tokio::select! {
+            result = BACKEND_PID.scope(backend_pid, fut) => result,
+            _ = cancelled.notified() => Err(AppError::Cancelled(format!(
+                "Query {} was cancelled",
+                request_id
+            ))),
+        }
+    }
+
+    /// Owner, database and backend process id of a running query
+    pub fn get(&self, request_id: &str) -> Option<(String, String, Option<i32>)> {
+        self.0.pin().get(request_id).map(|query| {
+            (
+                query.sub.clone(),
+                query.db_name.clone(),
+                query.backend_pid.get().copied(),
+            )
+        })
+    }
+
+    /// Stop waiting for a running query; returns false if it already finished
+    pub fn cancel(&self, request_id: &str) -> bool {
+        let queries = self.0.pin();
+        match queries.remove(request_id) {
+            Some(query) => {
+                // notify_one keeps a permit, so this works even if the query
+                // is not polling yet
+                query.cancelled.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Removes a query from the map when it finishes, however it finishes
+struct Deregister<'a> {
+    queries: &'a ActiveQueries,
+    request_id: &'a str,
+}
+
+impl Drop for Deregister<'_> {
+    fn drop(&mut self) {
+        self.queries.0.pin().remove(self.request_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_run_rejects_request_id_in_flight() {
+        let queries = ActiveQueries::default();
+        let first = queries.run(Some("dup"), "alice", "users", async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            Ok(())
+        });
+        let second = async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            let result = queries
+                .run(Some("dup"), "bob", "users", async { Ok(()) })
+                .await;
+            // The rejected run must leave the first one registered
+            (result, queries.get("dup"))
+        };
+        let (first, (second, running)) = tokio::join!(first, second);
+        assert!(first.is_ok());
+        assert!(matches!(second, Err(AppError::BadRequest(_))));
+        assert_eq!(
+            running,
+            Some(("alice".to_string(), "users".to_string(), None))
+        );
+        assert!(queries.get("dup").is_none());
+    }
+}
@@ -0,0 +1,180 @@
+use crate::{
+    config::{SavedQueriesConfig, SavedQueryStore},
+    error::AppError,
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{io::ErrorKind, path::PathBuf};
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+use tokio::sync::Mutex;
+
+/// A query a user saved to run again later. Only its owner can see it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SavedQuery {
+    pub id: String,
+    pub sub: String, // Token subject of the owner
+    pub name: String,
+    pub db_name: String,
+    pub query: String,
+    pub created_at: String, // RFC 3339, UTC
+}
+
+/// Saved queries of all users, kept in memory and, for the `file` store,
+/// written through to a JSON file
+pub struct SavedQueries {
+    path: Option<PathBuf>,
+    queries: Mutex<Vec<SavedQuery>>,
+}
+
+impl SavedQueries {
+    pub async fn try_new(config: &SavedQueriesConfig) -> Result<Self, anyhow::Error> {
+        let path = match config.store {
+            SavedQueryStore::Memory => None,
+            SavedQueryStore::File => Some(
+                config
+                    .path
+                    .clone()
+                    .context("saved_queries.path is required for the file saved query store")?,
+            ),
+        };
+        let queries = match &path {
+            Some(path) => match tokio::fs::read(path).await {
+                Ok(contents) => serde_json::from_slice(&contents).with_context(|| {
+                    format!("Failed to parse saved queries in {}", path.display())
+                })?,
+                // Created on the first save
+                Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+                Err(e) => {
+                    return Err(e).with_context(|| {
+                        format!("Failed to read saved queries from {}", path.display())
+                    });
+                }
+            },
+            None => Vec::new(),
+        };
+        Ok(SavedQueries {
+            path,
+            queries: Mutex::new(queries),
+        })
+    }
+
+    /// An empty store that is never written to disk
+    pub fn in_memory() -> Self {
+        SavedQueries {
+            path: None,
+            queries: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Saved queries of a user, oldest first
+    pub async fn list(&self, sub: &str) -> Vec<SavedQuery> {
+        let queries = self.queries.lock().await;
+        queries.iter().filter(|q| q.sub == sub).cloned().collect()
+    }
+
+    /// A saved query, if it exists and belongs to the user
+    pub async fn get(&self, sub: &str, id: &str) -> Option<SavedQuery> {
+        let queries = self.queries.lock().await;
+        queries.iter().find(|q| q.sub == sub && q.id == id).cloned()
+    }
+
+    /// Save a query for a user and return it with its new id
+    pub async fn insert(
+        &self,
+        sub: &str,
+        name: String,
+        db_name: String,
+        query: String,
+    ) -> Result<SavedQuery, AppError> {
+        let saved = SavedQuery {
+            id: uuid::Uuid::new_v4().to_string(),
+            sub: sub.to_string(),
+            name,
+            db_name,
+            query,
+            created_at: OffsetDateTime::now_utc()
+                .format(&Rfc3339)
+                .unwrap_or_default(),
+        };
+        let mut queries = self.queries.lock().await;
+        queries.push(saved.clone());
+        if let Err(e) = self.persist(&queries).await {
+            queries.pop();
+            return Err(e);
+        }
+        Ok(saved)
+    }
+
+    /// Delete a user's saved query; false if there was no such query
+    pub async fn remove(&self, sub: &str, id: &str) -> Result<bool, AppError> {
+        let mut queries = self.queries.lock().await;
+        let Some(index) = queries.iter().position(|q| q.sub == sub && q.id == id) else {
+            return Ok(false);
+        };
+        let removed = queries.remove(index);
+        if let Err(e) = self.persist(&queries).await {
+            queries.insert(index, removed);
+            return Err(e);
+        }
+        Ok(true)
+    }
+
+    /// Rewrite the file, if any. The new contents go to a temporary file
+    /// first, so a failed write never leaves a truncated store behind.
+    async fn persist(&self, queries: &[SavedQuery]) -> Result<(), AppError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let contents =
+            serde_json::to_vec_pretty(queries).map_err(|e| AppError::Storage(e.to_string()))?;
+        let tmp_path = path.with_extension("tmp");
+        tokio::fs::write(&tmp_path, contents)
+            .await
+            .map_err(|e| AppError::Storage(format!("{}: {}", tmp_path.display(), e)))?;
+        tokio::fs::rename(&tmp_path, path)
+            .await
+            .map_err(|e| AppError::Storage(format!("{}: {}", path.display(), e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file_store() {
+        let path = std::env::temp_dir().join(format!("r2-saved-{}.json", uuid::Uuid::new_v4()));
+        let config = SavedQueriesConfig {
+            store: SavedQueryStore::File,
+            path: Some(path.clone()),
+        };
+        let store = SavedQueries::try_new(&config).await.unwrap();
+        let saved = store
+            .insert(
+                "alice",
+                "All users".to_string(),
+                "users".to_string(),
+                "SELECT * FROM users".to_string(),
+            )
+            .await
+            .unwrap();
+        let other = store
+            .insert(
+                "alice",
+                "Count".to_string(),
+                "users".to_string(),
+                "SELECT count(*) FROM users".to_string(),
+            )
+            .await
+            .unwrap();
+        assert!(store.remove("alice", &other.id).await.unwrap());
+
+        // A new store sees what the first one wrote
+        let store = SavedQueries::try_new(&config).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(store.list("alice").await, vec![saved.clone()]);
+        assert!(store.list("bob").await.is_empty());
+        assert_eq!(store.get("bob", &saved.id).await, None);
+        assert!(!store.remove("bob", &saved.id).await.unwrap());
+    }
+}
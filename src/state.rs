@@ -1,20 +1,55 @@
-use crate::{AppConfig, DbPool, db::PoolHandler, error::AppError, handlers::FullSchema};
-use moka::future::Cache;
+use crate::{
+    AppConfig, Claims, DbPool,
+    ai::rig::AiClient,
+    audit::AuditLog,
+    auth::JwtValidator,
+    config::DatabaseConfig,
+    db::{PoolHandler, QueryResult, TableSchema},
+    error::AppError,
+    handlers::FullSchema,
+    metrics::Metrics,
+    queries::ActiveQueries,
+    saved_queries::SavedQueries,
+};
+use moka::{Expiry, future::Cache};
 use papaya::HashMap;
-use rig::providers::openai as rig_openai;
-use std::{ops::Deref, sync::Arc, time::Duration};
-use tracing::{error, info}; // Import with alias
+use std::{
+    ops::Deref,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::{error, info, warn}; // Import with alias
 
 #[derive(Clone)]
 pub struct AppState(Arc<AppStateInner>);
 
 pub struct AppStateInner {
     pub config: AppConfig,
+    // Registered databases: the configured ones plus any added at runtime
+    pub databases: HashMap<String, DatabaseConfig>,
     pub pools: Arc<HashMap<String, DbPool>>,
+    // Concurrent query slots of each registered database
+    pub query_slots: HashMap<String, Arc<Semaphore>>,
     // Cache for the full schema, storing the Result wrapped in Arc
     pub schema_cache: Cache<String, Arc<Result<FullSchema, AppError>>>,
+    // Cache for individual table schemas, keyed by "{db_name}/{table_name}"
+    pub table_schema_cache: Cache<String, Arc<TableSchema>>,
+    // Opt-in cache of SELECT results, keyed by (db_name, sanitized SQL,
+    // parameters as JSON, limit)
+    pub query_cache: Cache<(String, String, String, usize), Arc<QueryResult>>,
     // Add OpenAI client from rig-core
-    pub openai_client: rig_openai::Client,
+    pub ai_client: Option<AiClient>, // None when AI is disabled
+    // Verifies bearer tokens for the auth middleware
+    pub jwt_validator: JwtValidator,
+    // Query counters and latency histograms served at /metrics
+    pub metrics: Metrics,
+    // In-flight queries by request id, for cancellation
+    pub active_queries: ActiveQueries,
+    // Records of executed and generated queries
+    pub audit: AuditLog,
+    // Queries users saved to run again
+    pub saved_queries: SavedQueries,
 }
 
 // Manual Debug implementation because sqlx Pools don't implement Debug
@@ -24,7 +59,7 @@ impl std::fmt::Debug for AppStateInner {
             .field("config", &self.config)
             .field("db_pools_count", &self.pools.len()) // Only show count
             // Do not display the cache content
-            // Do not display the openai client details
+            // Do not display the AI client details
             .finish_non_exhaustive()
     }
 }
@@ -46,6 +81,7 @@ impl Deref for AppState {
 
 impl AppState {
     pub async fn new(config: AppConfig) -> Result<Self, anyhow::Error> {
+        let jwt_validator = JwtValidator::try_new(&config)?;
         let pools = HashMap::new();
 
         for db_config in &config.databases {
@@ -64,46 +100,298 @@ impl AppState {
         }
         info!("Database connections established.");
 
-        // Create the schema cache
+        // Create the schema caches
+        let schema_ttl = Duration::from_secs(config.schema_cache_ttl_secs);
         let schema_cache = Cache::builder()
-            // Time to live (TTL): 10 minutes
-            .time_to_live(Duration::from_secs(10 * 60))
+            .expire_after(SchemaExpiry {
+                ttl: schema_ttl,
+                error_ttl: SCHEMA_ERROR_TTL,
+            })
             // Max capacity (optional, e.g., only 1 entry needed)
             .max_capacity(1)
             .build();
+        let table_schema_cache = Cache::builder()
+            .time_to_live(schema_ttl)
+            .max_capacity(10_000)
+            .build();
+        let query_cache = Cache::builder()
+            .time_to_live(Duration::from_secs(config.cache_ttl_secs))
+            .max_capacity(1_000)
+            .build();
+
+        // Initialize the AI client; a missing API key is a startup error
+        let ai_client = if config.ai.enabled {
+            info!("Initializing {:?} AI client...", config.ai.provider);
+            let client = AiClient::try_new(&config.ai)?;
+            info!("AI client initialized (model: {}).", config.ai.model);
+            Some(client)
+        } else {
+            info!("AI query generation is disabled.");
+            None
+        };
 
-        // Initialize OpenAI client using environment variable
-        // This will panic if OPENAI_API_KEY is not set.
-        // Consider adding error handling or configuration check earlier.
-        info!("Initializing OpenAI client from environment...");
-        let openai_client = rig_openai::Client::from_env();
-        info!("OpenAI client initialized.");
+        let audit = AuditLog::try_new(&config.audit).await?;
+        let saved_queries = SavedQueries::try_new(&config.saved_queries).await?;
 
         let inner = AppStateInner {
+            databases: database_map(&config),
+            query_slots: query_slots_map(&config),
             config,
             pools: Arc::new(pools),
             schema_cache,
-            openai_client, // Add client to state
+            table_schema_cache,
+            query_cache,
+            ai_client,
+            jwt_validator,
+            metrics: Metrics::new(),
+            active_queries: ActiveQueries::default(),
+            audit,
+            saved_queries,
         };
         Ok(Self(Arc::new(inner)))
     }
 
+    /// Configuration of a registered database
+    pub fn database_config(&self, db_name: &str) -> Option<DatabaseConfig> {
+        self.databases.pin().get(db_name).cloned()
+    }
+
+    /// All registered databases, ordered by name
+    pub fn database_configs(&self) -> Vec<DatabaseConfig> {
+        let mut configs: Vec<DatabaseConfig> = self.databases.pin().values().cloned().collect();
+        configs.sort_by(|a, b| a.name.cmp(&b.name));
+        configs
+    }
+
+    /// Effective query timeout for a database: the per-database override if
+    /// set, otherwise the global value.
+    pub fn query_timeout(&self, db_name: &str) -> Option<Duration> {
+        self.databases
+            .pin()
+            .get(db_name)
+            .and_then(|db| db.query_timeout_secs)
+            .or(self.config.query_timeout_secs)
+            .map(Duration::from_secs)
+    }
+
+    /// Give a newly registered database its concurrent query slots
+    pub fn add_query_slots(&self, db_config: &DatabaseConfig) {
+        self.query_slots
+            .pin()
+            .insert(db_config.name.clone(), query_slots(&self.config, db_config));
+    }
+
+    /// Wait up to `QUERY_SLOT_TIMEOUT` for a free concurrent query slot of
+    /// the database; the query holds it until the permit is dropped.
+    /// Unknown databases have no slots and get no permit.
+    pub async fn acquire_query_slot(
+        &self,
+        db_name: &str,
+    ) -> Result<Option<OwnedSemaphorePermit>, AppError> {
+        let Some(slots) = self.query_slots.pin().get(db_name).cloned() else {
+            return Ok(None);
+        };
+        match tokio::time::timeout(QUERY_SLOT_TIMEOUT, slots.acquire_owned()).await {
+            Ok(Ok(permit)) => Ok(Some(permit)),
+            // Semaphores are never closed
+            Ok(Err(_)) | Err(_) => {
+                warn!("No free query slot for database '{}'", db_name);
+                Err(AppError::Overloaded(format!(
+                    "Too many concurrent queries on database '{}', try again later",
+                    db_name
+                )))
+            }
+        }
+    }
+
+    /// Whether mutations are rejected for the given database; unknown
+    /// databases are treated as read-only
+    pub fn is_read_only(&self, db_name: &str) -> bool {
+        self.databases
+            .pin()
+            .get(db_name)
+            .is_none_or(|db| db.read_only)
+    }
+
+    /// Whether the caller holds one of the roles the database requires.
+    /// Unknown databases are let through, so they are reported as not found.
+    pub fn can_access(&self, claims: &Claims, db_name: &str) -> bool {
+        self.databases.pin().get(db_name).is_none_or(|db| {
+            db.required_roles.is_empty()
+                || db
+                    .required_roles
+                    .iter()
+                    .any(|role| claims.roles.contains(role))
+        })
+    }
+
+    /// Fail with 403 unless the caller may use the database (see `can_access`)
+    pub fn authorize(&self, claims: &Claims, db_name: &str) -> Result<(), AppError> {
+        if self.can_access(claims, db_name) {
+            Ok(())
+        } else {
+            warn!("Rejected access to '{}' by '{}'", db_name, claims.sub);
+            Err(AppError::Forbidden(format!(
+                "Access to database '{}' is forbidden",
+                db_name
+            )))
+        }
+    }
+
+    /// Close every database pool; called once the server has stopped
+    pub async fn close_pools(&self) {
+        let pools = self.pools.pin_owned();
+        for (name, pool) in pools.iter() {
+            info!("Closing connection pool for database '{}'", name);
+            pool.close().await;
+        }
+    }
+
     #[cfg(test)]
     pub fn new_for_test(config: AppConfig) -> Self {
         // Create empty/dummy versions of fields not needed for config-only tests
         let pools = Arc::new(HashMap::new());
         let schema_cache = Cache::builder().build();
-        // Initialize client from env - it won't be used in config-only tests.
-        // This might panic if OPENAI_API_KEY is *required* and *not set* during init,
-        // but typically `from_env` reads it lazily or handles its absence until first use.
-        let openai_client = rig_openai::Client::from_env();
+        let table_schema_cache = Cache::builder().build();
+        let query_cache = Cache::builder().build();
+        // The AI client is not used in config-only tests, so a missing key is fine
+        let ai_client = AiClient::try_new(&config.ai).ok();
+        let jwt_validator = JwtValidator::try_new(&config).expect("invalid JWT configuration");
 
         let inner = AppStateInner {
+            databases: database_map(&config),
+            query_slots: query_slots_map(&config),
             config,
             pools,
             schema_cache,
-            openai_client,
+            table_schema_cache,
+            query_cache,
+            ai_client,
+            jwt_validator,
+            metrics: Metrics::new(),
+            active_queries: ActiveQueries::default(),
+            audit: AuditLog::Off,
+            saved_queries: SavedQueries::in_memory(),
         };
         Self(Arc::new(inner))
     }
 }
+
+/// How long a query waits for a concurrent query slot before it is rejected
+const QUERY_SLOT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long a failed full-schema fetch stays cached, so a transient outage
+/// is retried soon instead of hiding the schema for the whole TTL
+const SCHEMA_ERROR_TTL: Duration = Duration::from_secs(15);
+
+/// Expiry of full-schema cache entries: `ttl` for schemas, and `error_ttl`
+/// (or `ttl` if shorter) for errors
+struct SchemaExpiry {
+    ttl: Duration,
+    error_ttl: Duration,
+}
+
+impl Expiry<String, Arc<Result<FullSchema, AppError>>> for SchemaExpiry {
+    fn expire_after_create(
+        &self,
+        _key: &String,
+        value: &Arc<Result<FullSchema, AppError>>,
+        _created_at: Instant,
+    ) -> Option<Duration> {
+        match **value {
+            Ok(_) => Some(self.ttl),
+            Err(_) => Some(self.ttl.min(self.error_ttl)),
+        }
+    }
+}
+
+fn database_map(config: &AppConfig) -> HashMap<String, DatabaseConfig> {
+    let databases = HashMap::new();
+    for db_config in &config.databases {
+        databases
+            .pin()
+            .insert(db_config.name.clone(), db_config.clone());
+    }
+    databases
+}
+
+fn query_slots_map(config: &AppConfig) -> HashMap<String, Arc<Semaphore>> {
+    let slots = HashMap::new();
+    for db_config in &config.databases {
+        slots
+            .pin()
+            .insert(db_config.name.clone(), query_slots(config, db_config));
+    }
+    slots
+}
+
+fn query_slots(config: &AppConfig, db_config: &DatabaseConfig) -> Arc<Semaphore> {
+    let size = db_config
+        .max_concurrent_queries
+        .unwrap_or(config.max_concurrent_queries);
+    Arc::new(Semaphore::new(size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_schema_errors_expire_sooner() {
+        let cache = Cache::builder()
+            .expire_after(SchemaExpiry {
+                ttl: Duration::from_secs(600),
+                error_ttl: Duration::from_millis(50),
+            })
+            .build();
+        let fetches = AtomicUsize::new(0);
+        let fetch = |result: Result<FullSchema, AppError>| {
+            let cache = &cache;
+            let fetches = &fetches;
+            async move {
+                cache
+                    .get_with("full_schema".to_string(), async {
+                        fetches.fetch_add(1, Ordering::SeqCst);
+                        Arc::new(result)
+                    })
+                    .await
+            }
+        };
+        let error = || Err(AppError::DatabaseMessage("timed out".to_string()));
+        let schema = || {
+            Ok(FullSchema {
+                databases: Vec::new(),
+            })
+        };
+
+        // A failed fetch is retried once the short error TTL has passed
+        assert!(fetch(error()).await.is_err());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(fetch(schema()).await.is_ok());
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+
+        // A successful one is reused
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(fetch(error()).await.is_ok());
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_query_slots() {
+        let mut config = AppConfig::load("./config").unwrap();
+        config.databases[0].max_concurrent_queries = Some(1);
+        let db_name = config.databases[0].name.clone();
+        let state = AppState::new_for_test(config);
+
+        let permit = state.acquire_query_slot(&db_name).await.unwrap();
+        assert!(permit.is_some());
+        let result = state.acquire_query_slot(&db_name).await;
+        assert!(matches!(result, Err(AppError::Overloaded(_))));
+
+        // The slot is free again once the first query is done
+        drop(permit);
+        assert!(state.acquire_query_slot(&db_name).await.unwrap().is_some());
+        assert!(state.acquire_query_slot("unknown").await.unwrap().is_none());
+    }
+}
@@ -24,9 +24,12 @@ export interface TableSchema { // Assuming this matches backend DatabaseSchema t
   // Add constraints if available from backend
 }
 
+export type DatabaseKind = 'postgres' | 'mysql' | 'sqlite' | 'clickhouse' | 'mssql';
+
 export interface DatabaseSchema { // Assuming this matches backend FullSchema databases element
   name: string;
-  db_type: string;
+  db_type: string; // Deprecated: use kind
+  kind: DatabaseKind;
   tables: TableSchema[];
 }
 
@@ -63,6 +66,9 @@ export interface QueryResultData {
   affectedRows?: number;
   executionTime?: number; // In seconds
   plan?: unknown; // Use unknown
+  row_count?: number;
+  columns?: string[]; // Keys of the first row
+  truncated?: boolean; // True when the row limit cut the result short
 }
 
 // --- NEW: Query Tab State Interface ---